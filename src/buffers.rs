@@ -0,0 +1,186 @@
+use crate::*;
+
+/// Name of the buffer in the status bar and the buffer list.
+pub fn buffer_name(buf: &EditBuffer) -> &str {
+    if buf.file_name().is_empty() {
+        "[NEW FILE]"
+    } else {
+        buf.file_name()
+    }
+}
+
+/// List of the buffers with their modified flags.
+/// The displayed buffer is owned by the editor, so its slot is empty.
+pub struct BufferList {
+    slots: Vec<Option<(EditBuffer, bool)>>,
+    current: usize, // index of the displayed buffer
+}
+
+impl Default for BufferList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BufferList {
+    pub fn new() -> Self {
+        BufferList {
+            slots: vec![None],
+            current: 0,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+    pub fn current(&self) -> usize {
+        self.current
+    }
+    /// the buffer at `index` which is not displayed. None for the displayed one.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut EditBuffer> {
+        match self.slots.get_mut(index) {
+            Some(Some((buf, _))) => Some(buf),
+            _ => None,
+        }
+    }
+    /// add a buffer at the end of the list. Returns its index.
+    pub fn push(&mut self, buf: EditBuffer, changed: bool) -> usize {
+        self.slots.push(Some((buf, changed)));
+        self.slots.len() - 1
+    }
+    /// display the buffer at `index`. `buf` and `changed` of the displayed buffer are
+    /// exchanged with it, and the buffer takes over the window.
+    pub fn switch(&mut self, index: usize, buf: &mut EditBuffer, changed: &mut bool) {
+        if index == self.current || index >= self.slots.len() {
+            return;
+        }
+        let (mut next, next_changed) = self.slots[index].take().unwrap();
+        let window = buf.window().clone();
+        std::mem::swap(buf, &mut next);
+        buf.resize(window);
+        self.slots[self.current] = Some((next, *changed));
+        *changed = next_changed;
+        self.current = index;
+    }
+    /// remove the displayed buffer, and display the next one, or the previous one at the end.
+    /// Returns false if it is the only buffer.
+    pub fn close(&mut self, buf: &mut EditBuffer, changed: &mut bool) -> bool {
+        if self.slots.len() == 1 {
+            return false;
+        }
+        let closed = self.current;
+        let next = if closed + 1 < self.slots.len() {
+            closed + 1
+        } else {
+            closed - 1
+        };
+        self.switch(next, buf, changed);
+        self.slots.remove(closed);
+        if closed < self.current {
+            self.current -= 1;
+        }
+        true
+    }
+    /// names and modified flags of all buffers. `buf` is the displayed buffer.
+    pub fn list(&self, buf: &EditBuffer, changed: bool) -> Vec<(String, bool)> {
+        self.slots
+            .iter()
+            .map(|slot| match slot {
+                Some((buf, changed)) => (buffer_name(buf).to_string(), *changed),
+                None => (buffer_name(buf).to_string(), changed),
+            })
+            .collect()
+    }
+    /// index of the buffer named `name`.
+    pub fn find(&self, name: &str, buf: &EditBuffer) -> Option<usize> {
+        self.list(buf, false).iter().position(|(n, _)| n == name)
+    }
+    /// some buffer which is not displayed is modified.
+    pub fn any_modified(&self) -> bool {
+        self.slots.iter().flatten().any(|(_, changed)| *changed)
+    }
+    /// complete `prefix` to the longest common prefix of the buffer names starting with it.
+    pub fn complete(&self, prefix: &str, buf: &EditBuffer) -> String {
+        let names: Vec<String> = self
+            .list(buf, false)
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        let first = match names.first() {
+            Some(first) => first,
+            None => return prefix.to_string(),
+        };
+        let mut common = first.clone();
+        for name in &names[1..] {
+            while !name.starts_with(&common) {
+                common.pop();
+            }
+        }
+        common
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(file_name: &str) -> EditBuffer {
+        let layout = Screen {
+            width: 80,
+            height: 24,
+        }
+        .layout();
+        let mut buf = EditBuffer::new(layout.editor);
+        buf.new_buffer();
+        buf.set_file_name(file_name);
+        buf
+    }
+
+    #[test]
+    fn switch_and_close() {
+        let mut list = BufferList::new();
+        let mut buf = buffer("a.txt");
+        let mut changed = true;
+        list.push(buffer("b.txt"), false);
+        list.push(buffer(""), false);
+        let names: Vec<String> = list
+            .list(&buf, changed)
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(names, ["a.txt", "b.txt", "[NEW FILE]"]);
+        list.switch(1, &mut buf, &mut changed);
+        assert_eq!(
+            (buf.file_name(), changed, list.current()),
+            ("b.txt", false, 1)
+        );
+        assert!(list.any_modified());
+        assert_eq!(list.find("a.txt", &buf), Some(0));
+        assert_eq!(list.list(&buf, changed)[0], (String::from("a.txt"), true));
+        // the next buffer is displayed after closing.
+        assert!(list.close(&mut buf, &mut changed));
+        assert_eq!((buf.file_name(), list.current(), list.len()), ("", 1, 2));
+        // the previous one at the end of the list.
+        assert!(list.close(&mut buf, &mut changed));
+        assert_eq!(
+            (buf.file_name(), changed, list.current()),
+            ("a.txt", true, 0)
+        );
+        assert!(!list.close(&mut buf, &mut changed));
+        assert!(!list.any_modified());
+    }
+    #[test]
+    fn complete_buffer_names() {
+        let mut list = BufferList::new();
+        let buf = buffer("src/main.rs");
+        list.push(buffer("src/lib.rs"), false);
+        list.push(buffer("readme.md"), false);
+        assert_eq!(list.complete("s", &buf), "src/");
+        assert_eq!(list.complete("src/l", &buf), "src/lib.rs");
+        assert_eq!(list.complete("x", &buf), "x");
+        assert_eq!(list.complete("", &buf), "");
+    }
+}
@@ -0,0 +1,210 @@
+use std::env;
+use std::io::{Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::ClipboardConfig;
+
+/// a copy or paste command taking longer is killed, not to freeze the editor.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// System clipboard.
+/// Copied text is sent to the terminal by OSC 52 escape sequence, which also works over SSH,
+/// and optionally piped to a local command such as `xclip` or `wl-copy`.
+/// Pasted text is read from a local command such as `xclip -o` or `wl-paste`.
+pub struct Clipboard {
+    copy_command: Option<String>,
+    paste_command: Option<String>,
+    timeout: Duration, // of the copy and paste commands
+}
+
+impl Clipboard {
+    pub fn new(copy_command: Option<String>, paste_command: Option<String>) -> Self {
+        Clipboard {
+            copy_command,
+            paste_command,
+            timeout: COMMAND_TIMEOUT,
+        }
+    }
+    /// commands are taken from `KED_CLIPBOARD_COPY` and `KED_CLIPBOARD_PASTE`.
+    pub fn from_env() -> Self {
+        Self::from_config(&ClipboardConfig::default())
+    }
+    /// commands of the config, overridden by `KED_CLIPBOARD_COPY` and `KED_CLIPBOARD_PASTE`.
+    pub fn from_config(config: &ClipboardConfig) -> Self {
+        let command = |var: &str, config: &str| {
+            env::var(var)
+                .ok()
+                .filter(|s| !s.is_empty())
+                .or_else(|| Some(config.to_string()).filter(|s| !s.is_empty()))
+        };
+        Self::new(
+            command("KED_CLIPBOARD_COPY", &config.copy),
+            command("KED_CLIPBOARD_PASTE", &config.paste),
+        )
+    }
+    /// copy `text` to the terminal's clipboard and the local clipboard command.
+    pub fn copy<W: Write>(&self, text: &str, output: &mut W) {
+        write!(output, "{}", osc52(text)).unwrap();
+        output.flush().unwrap();
+        if let Some(command) = &self.copy_command {
+            if !self.run_copy(command, text) {
+                eprintln!("clipboard copy command failed:{}", command);
+            }
+        }
+    }
+    fn run_copy(&self, command: &str, text: &str) -> bool {
+        let mut child = match Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(_) => return false,
+        };
+        // a command which does not read stdin must not block the editor.
+        if let Some(mut stdin) = child.stdin.take() {
+            let text = text.to_string();
+            thread::spawn(move || stdin.write_all(text.as_bytes()).ok());
+        }
+        wait_timeout(&mut child, self.timeout).is_some_and(|status| status.success())
+    }
+    /// text in the local clipboard. None if no paste command is configured,
+    /// or it failed or did not finish in time.
+    pub fn paste(&self) -> Option<String> {
+        let command = self.paste_command.as_ref()?;
+        let text = self.run_paste(command);
+        if text.is_none() {
+            eprintln!("clipboard paste command failed:{}", command);
+        }
+        text
+    }
+    fn run_paste(&self, command: &str) -> Option<String> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut stdout = child.stdout.take()?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut bytes = vec![];
+            let result = stdout.read_to_end(&mut bytes).map(|_| bytes);
+            tx.send(result).ok();
+        });
+        if !wait_timeout(&mut child, self.timeout)?.success() {
+            return None;
+        }
+        // the output may be still open in a process started by the command.
+        let bytes = rx.recv_timeout(self.timeout).ok()?.ok()?;
+        Some(String::from_utf8_lossy(&bytes).into())
+    }
+}
+
+/// wait for the child process to exit, at most `timeout`. Kills it on timeout.
+fn wait_timeout(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) if start.elapsed() < timeout => thread::sleep(Duration::from_millis(5)),
+            Ok(None) => break,
+            Err(_) => return None,
+        }
+    }
+    child.kill().ok();
+    child.wait().ok();
+    None
+}
+
+/// OSC 52 escape sequence to set the clipboard of the terminal.
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::new();
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("あ".as_bytes()), "44GC");
+    }
+    #[test]
+    fn osc52_sequence() {
+        let clipboard = Clipboard::new(None, None);
+        let mut out: Vec<u8> = vec![];
+        clipboard.copy("foo", &mut out);
+        assert_eq!(out, b"\x1b]52;c;Zm9v\x07");
+        assert_eq!(clipboard.paste(), None);
+    }
+    #[test]
+    fn copy_and_paste_commands() {
+        let file = env::temp_dir().join("ked_test_clipboard.txt");
+        let file = file.to_str().unwrap();
+        let config = ClipboardConfig {
+            copy: format!("cat > {}", file),
+            paste: format!("cat {}", file),
+        };
+        let clipboard = Clipboard::from_config(&config);
+        clipboard.copy("foo\nあ", &mut std::io::sink());
+        assert_eq!(clipboard.paste().as_deref(), Some("foo\nあ"));
+        std::fs::remove_file(file).ok();
+        assert_eq!(clipboard.paste(), None);
+    }
+    #[test]
+    fn copy_command_times_out() {
+        let clipboard = Clipboard {
+            timeout: Duration::from_millis(100),
+            ..Clipboard::new(Some(String::from("sleep 5")), None)
+        };
+        let start = Instant::now();
+        let mut out: Vec<u8> = vec![];
+        clipboard.copy("foo", &mut out);
+        assert!(start.elapsed() < Duration::from_secs(4));
+        assert_eq!(out, b"\x1b]52;c;Zm9v\x07");
+    }
+    #[test]
+    fn paste_command_times_out() {
+        let clipboard = Clipboard {
+            timeout: Duration::from_millis(100),
+            ..Clipboard::new(None, Some(String::from("sleep 10")))
+        };
+        let start = Instant::now();
+        assert_eq!(clipboard.paste(), None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::keymap::Keymaps;
+
+/// Name of the per-project config file, searched from the directory of the edited file upward.
+pub const PROJECT_CONFIG: &str = ".ked.json";
+
+/// Editor settings, read from a JSON file. Every field is optional.
+///
+/// ```json
+/// {"tab_width": 4, "insert_mode": true, "autosave": 30, "vi_mode": false,
+///  "line_numbers": "relative", "wrap": "word",
+///  "colors": {"status_fg": 0, "status_bg": 7},
+///  "clipboard": {"copy": "xclip -selection clipboard", "paste": "xclip -o -selection clipboard"},
+///  "keymap": {"editor": {"C-x C-f": "search_forward", "C-f": ""}}}
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub tab_width: usize,  // columns of a tab stop, 1 to 16
+    pub insert_mode: bool, // start in insert mode, overwrite mode if false
+    pub autosave: u64,     // seconds between saves of a modified file, 0 to disable
+    pub vi_mode: bool,     // vi-like modal editing, starting in normal mode
+    pub line_numbers: LineNumbers,
+    pub wrap: Wrap,
+    pub colors: Colors,
+    pub clipboard: ClipboardConfig,
+    pub keymap: KeymapConfig,
+}
+
+/// Shell commands for the local clipboard. Empty to use only the terminal's clipboard.
+/// Only the user config sets them, not the project config.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipboardConfig {
+    pub copy: String,  // reads the copied text from stdin
+    pub paste: String, // writes the text to paste to stdout
+}
+
+/// Key sequences to command names for each mode, overriding the default keymaps.
+/// An empty command name unbinds the keys.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeymapConfig {
+    pub editor: BTreeMap<String, String>,
+    pub prompt: BTreeMap<String, String>,
+    pub search: BTreeMap<String, String>,
+    pub one_key: BTreeMap<String, String>,
+}
+
+/// Line numbers in the gutter left of the text: "off", "absolute" or "relative".
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative, // distance from the cursor line, which shows its own number
+}
+
+/// Soft wrap of lines longer than the window: "off" to scroll horizontally,
+/// "char" to wrap at any char, or "word" to wrap after a space if possible.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    Off,
+    Char,
+    Word,
+}
+
+/// Colors in the 256 color palette of the terminal.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    pub status_fg: u8,
+    pub status_bg: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            tab_width: 8,
+            insert_mode: true,
+            autosave: 0,
+            vi_mode: false,
+            line_numbers: LineNumbers::Off,
+            wrap: Wrap::Off,
+            colors: Colors::default(),
+            clipboard: ClipboardConfig::default(),
+            keymap: KeymapConfig::default(),
+        }
+    }
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            status_fg: 0,
+            status_bg: 7,
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/ked`, or `~/.config/ked`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("ked"))
+}
+
+impl Config {
+    /// the user config file: `$XDG_CONFIG_HOME/ked/config`
+    pub fn user_file() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config"))
+    }
+    /// parse and validate settings in JSON.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        Self::from_value(value)
+    }
+    fn from_value(value: Value) -> Result<Self, String> {
+        let config: Config = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+    fn validate(&self) -> Result<(), String> {
+        if !(1..=16).contains(&self.tab_width) {
+            return Err(format!(
+                "tab_width must be between 1 and 16, but {}",
+                self.tab_width
+            ));
+        }
+        Keymaps::new(&self.keymap)?;
+        Ok(())
+    }
+    /// load the user config file, or `config_file` instead if given,
+    /// then override by the project config file found from `dir` upward.
+    /// A missing user config file is not an error.
+    pub fn load(config_file: Option<&str>, dir: &Path) -> Result<Self, String> {
+        let mut value = Value::Object(Default::default());
+        let user_file = match config_file {
+            Some(file) => Some(PathBuf::from(file)),
+            None => Self::user_file().filter(|file| file.exists()),
+        };
+        if let Some(file) = user_file {
+            merge(&mut value, read_file(&file)?);
+        }
+        let project_file = dir
+            .ancestors()
+            .map(|dir| dir.join(PROJECT_CONFIG))
+            .find(|file| file.exists());
+        if let Some(file) = project_file {
+            let mut project = read_file(&file)?;
+            // a project should not run commands of its choice.
+            if let Value::Object(project) = &mut project {
+                project.remove("clipboard");
+            }
+            merge(&mut value, project);
+        }
+        Self::from_value(value)
+    }
+}
+
+/// read a config file. Errors are reported with the file name.
+fn read_file(file: &Path) -> Result<Value, String> {
+    let error = |e: String| format!("{}:{}", file.display(), e);
+    let json = fs::read_to_string(file).map_err(|e| error(e.to_string()))?;
+    let value: Value = serde_json::from_str(&json).map_err(|e| error(e.to_string()))?;
+    Config::from_value(value.clone()).map_err(error)?;
+    Ok(value)
+}
+
+/// override fields of `base` by `value`. Objects are merged recursively.
+fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(value)) => {
+            for (key, v) in value {
+                merge(base.entry(key).or_insert(Value::Null), v);
+            }
+        }
+        (base, value) => *base = value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_config() {
+        let config = Config::from_json(r#"{"tab_width": 4, "colors": {"status_bg": 4}}"#).unwrap();
+        assert_eq!(config.tab_width, 4);
+        assert_eq!(config.colors.status_bg, 4);
+        assert_eq!(config.colors.status_fg, 0);
+        assert!(config.insert_mode);
+        assert_eq!(Config::from_json("{}").unwrap(), Config::default());
+        let config = Config::from_json(r#"{"line_numbers": "relative"}"#).unwrap();
+        assert_eq!(config.line_numbers, LineNumbers::Relative);
+        let config = Config::from_json(r#"{"wrap": "word"}"#).unwrap();
+        assert_eq!(config.wrap, Wrap::Word);
+        let config = Config::from_json(r#"{"clipboard": {"paste": "wl-paste"}}"#).unwrap();
+        assert_eq!(config.clipboard.paste, "wl-paste");
+        assert_eq!(config.clipboard.copy, "");
+    }
+    #[test]
+    fn invalid_config() {
+        let e = Config::from_json(r#"{"tab_widht": 4}"#).unwrap_err();
+        assert!(e.starts_with("unknown field `tab_widht`"), "{}", e);
+        let e = Config::from_json(r#"{"tab_width": 0}"#).unwrap_err();
+        assert_eq!(e, "tab_width must be between 1 and 16, but 0");
+        let e = Config::from_json(r#"{"colors": {"status_fg": 256}}"#).unwrap_err();
+        assert!(e.contains("expected u8"), "{}", e);
+        let e = Config::from_json(r#"{"insert_mode": "no"}"#).unwrap_err();
+        assert!(e.contains("expected a boolean"), "{}", e);
+        let e = Config::from_json(r#"{"line_numbers": "on"}"#).unwrap_err();
+        assert!(e.starts_with("unknown variant `on`"), "{}", e);
+        let e = Config::from_json(r#"{"keymap": {"editor": {"C-t": "no_such"}}}"#).unwrap_err();
+        assert_eq!(e, "keymap.editor:C-t:unknown command no_such");
+    }
+    #[test]
+    fn project_config_overrides() {
+        let dir = env::temp_dir().join("ked_test_config");
+        let sub_dir = dir.join("src");
+        fs::create_dir_all(&sub_dir).unwrap();
+        let config_file = dir.join("config");
+        fs::write(
+            &config_file,
+            r#"{"tab_width": 2, "autosave": 10, "colors": {"status_fg": 3},
+                "clipboard": {"copy": "wl-copy"}}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join(PROJECT_CONFIG),
+            r#"{"tab_width": 4, "colors": {"status_bg": 1}, "clipboard": {"copy": "project-copy"}}"#,
+        )
+        .unwrap();
+        let config = Config::load(config_file.to_str(), &sub_dir).unwrap();
+        assert_eq!(config.tab_width, 4);
+        assert_eq!(config.autosave, 10);
+        assert_eq!((config.colors.status_fg, config.colors.status_bg), (3, 1));
+        assert_eq!(config.clipboard.copy, "wl-copy");
+
+        fs::write(dir.join(PROJECT_CONFIG), r#"{"tab_width": 17}"#).unwrap();
+        let e = Config::load(config_file.to_str(), &sub_dir).unwrap_err();
+        assert!(
+            e.ends_with(".ked.json:tab_width must be between 1 and 16, but 17"),
+            "{}",
+            e
+        );
+        fs::remove_dir_all(&dir).ok();
+
+        let e = Config::load(Some("no_such_config"), Path::new("/")).unwrap_err();
+        assert!(e.starts_with("no_such_config:"), "{}", e);
+    }
+}
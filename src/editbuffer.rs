@@ -1,4 +1,6 @@
+use std::collections::BTreeMap;
 use std::io::Write;
+use std::ops::Range;
 use termion::*;
 
 use std::fs::File;
@@ -6,13 +8,15 @@ use std::io::{BufRead, BufReader};
 
 use std::str;
 
+use regex::Regex;
 use unicode_width::*;
 
+use crate::text::{char_class, CharClass};
 use crate::*;
 
-/// Edit buffer. current impriment is Vec<String>
+/// Edit buffer. Text is stored in `Text`(rope).
 pub struct EditBuffer {
-    buffer: Vec<String>,
+    buffer: Text,
     begin: usize, // line to start display
     cur_x: usize, // 0-index-ed buffer coodinates. char counting.
     cur_y: usize,
@@ -20,12 +24,23 @@ pub struct EditBuffer {
     window: Window, // Window information is cloned at the initalizing.
     cache_width: Vec<usize>,
     cache_size: Vec<usize>,
+    history: UndoHistory,
+    highlight: Option<Regex>, // search pattern to be highlighted
+    mark: Option<usize>,      // char offset of the mark
+    tab_width: usize,
+    line_numbers: LineNumbers,
+    markers: BTreeMap<usize, char>, // marker in the gutter for each line
+    numbered_y: usize,              // cursor line when the relative line numbers were drawn
+    left_column: usize,             // first display column shown, for lines longer than the window
+    wrap: Wrap,
+    begin_row: usize,    // first row of the `begin` line shown, if wrapped
+    drawn_column: usize, // `left_column` when the lines were drawn
 }
 
 impl EditBuffer {
     pub fn new(win: Window) -> Self {
         Self {
-            buffer: vec![] as Vec<String>,
+            buffer: Text::new(),
             begin: 0,
             cur_x: 0, // cursor point of the buffer(0-indexed)
             cur_y: 0,
@@ -33,17 +48,29 @@ impl EditBuffer {
             window: win,
             cache_width: vec![] as Vec<usize>,
             cache_size: vec![] as Vec<usize>,
+            history: UndoHistory::new(),
+            highlight: None,
+            mark: None,
+            tab_width: 8,
+            line_numbers: LineNumbers::Off,
+            markers: BTreeMap::new(),
+            numbered_y: 0,
+            left_column: 0,
+            drawn_column: 0,
+            wrap: Wrap::Off,
+            begin_row: 0,
         }
     }
     pub fn load_file(&mut self, file_name: &str) -> Result<&Self, &str> {
         if let Ok(file) = File::open(file_name) {
-            for result in BufReader::new(file).lines() {
-                match result {
-                    Ok(s) => self.buffer.push(s.clone()),
-                    Err(_) => self.buffer.push(String::new()),
-                }
-            }
+            self.buffer = Text::from_lines(
+                BufReader::new(file)
+                    .lines()
+                    .map(|result| result.unwrap_or_default()),
+            );
             self.file_name = file_name.to_string();
+            self.history = UndoHistory::load(file_name, &self.buffer).unwrap_or_default();
+            self.mark = None;
             self.calc_line();
             Ok(self)
         } else {
@@ -51,16 +78,15 @@ impl EditBuffer {
         }
     }
     pub fn save_file(&mut self) -> Result<&Self, &str> {
-        if self.file_name == "" {
+        if self.file_name.is_empty() {
             eprintln!("save_file: No File Name");
             Err("No File Name")
         } else {
             let mut file = File::create(self.file_name.clone());
             match &mut file {
                 Ok(file) => {
-                    for line in &self.buffer {
-                        writeln!(file, "{}", line).unwrap();
-                    }
+                    self.buffer.write_to(file).unwrap();
+                    self.save_history();
                     Ok(self)
                 }
                 Err(_) => {
@@ -70,19 +96,30 @@ impl EditBuffer {
             }
         }
     }
-    pub fn save_file_as(&mut self, file_name: &str) {
+    pub fn save_file_as(&mut self, file_name: &str) -> Result<&Self, &str> {
         if let Ok(mut file) = File::create(file_name) {
             eprintln!("save_file_as:{}", file_name);
-            for line in &self.buffer {
-                writeln!(file, "{}", line).unwrap();
-            }
+            self.buffer.write_to(&mut file).unwrap();
             self.file_name = file_name.to_string();
+            self.save_history();
+            Ok(self)
         } else {
             eprintln!("save_file_as:can not write:{}", file_name);
+            Err("Cannot create file")
+        }
+    }
+    fn save_history(&self) {
+        if let Err(e) = self.history.save(&self.file_name, &self.buffer) {
+            eprintln!("Cannot save undo history:{}:{}", self.file_name, e);
         }
     }
     pub fn new_buffer(&mut self) {
-        self.buffer = vec![String::from("")];
+        self.buffer = Text::new();
+        self.history.clear();
+        self.mark = None;
+    }
+    pub fn text(&self) -> &Text {
+        &self.buffer
     }
     pub fn file_name(&self) -> &str {
         &self.file_name
@@ -90,6 +127,20 @@ impl EditBuffer {
     pub fn set_file_name(&mut self, file_name: &str) {
         self.file_name = file_name.to_string();
     }
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+    }
+    /// display width of `c` at `column`. A tab extends to the next tab stop.
+    fn char_width(&self, c: char, column: usize) -> usize {
+        if c == '\t' {
+            self.tab_width - column % self.tab_width
+        } else {
+            c.width().unwrap_or(0)
+        }
+    }
     /// return cursor x position on the buffer coodinate.
     pub fn cur_x(&self) -> usize {
         self.cur_x
@@ -107,38 +158,233 @@ impl EditBuffer {
         }
     }
     pub fn update_win_cur(&mut self) {
+        self.window.set_left(self.gutter_width());
         self.calc_line();
         self.set_cur_x(self.cur_x);
-        let mut cursor_x = 0;
-        for i in 0..self.cur_x {
-            cursor_x += self.cache_width[i];
+        if self.wrap != Wrap::Off {
+            self.left_column = 0;
+            let (row, column) = self.cursor_row();
+            self.scroll_to_row(row);
+            self.window.set_cur_x(column as u16);
+            self.window
+                .set_cur_y(self.rows_from_top(self.cur_y, row) as u16);
+            return;
         }
-        self.window.set_cur_x(cursor_x as u16);
+        let cursor_x = self.cache_width[..self.cur_x].iter().sum();
+        self.scroll_to_column(cursor_x);
+        self.window.set_cur_x((cursor_x - self.left_column) as u16);
         if self.cur_y >= self.begin {
             self.window.set_cur_y((self.cur_y - self.begin) as u16);
         }
     }
+    /// adjust `left_column` so that the cursor at display column `column` is in the window.
+    /// The cursor is kept off the columns of the cut-off indicators.
+    fn scroll_to_column(&mut self, column: usize) {
+        let width = self.window.text_width() as usize;
+        let cursor_width = self.cache_width[self.cur_x].max(1);
+        let right = (self.cur_x + 1 < self.current_line_len()) as usize; // for '>'
+        if column + cursor_width + right > self.left_column + width {
+            self.left_column = (column + cursor_width + right).saturating_sub(width);
+        }
+        if self.left_column > 0 && column <= self.left_column {
+            // for '<'
+            self.left_column = column.saturating_sub(1);
+        }
+    }
+    pub fn wrap(&self) -> Wrap {
+        self.wrap
+    }
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+        self.begin_row = 0;
+        self.update_win_cur();
+    }
+    /// start char index of each display row of line `y`. A row if not wrapped.
+    /// A wide char is not split, and `Wrap::Word` wraps after the last space in the row.
+    fn line_rows(&self, y: usize) -> Vec<usize> {
+        let mut rows = vec![0];
+        if self.wrap == Wrap::Off || y >= self.buffer.len_lines() {
+            return rows;
+        }
+        let width = self.window.text_width() as usize;
+        let chars: Vec<char> = self.buffer.line(y).chars().collect();
+        let (mut start, mut column, mut i) = (0, 0, 0);
+        while i < chars.len() {
+            let w = self.char_width(chars[i], column);
+            if column + w > width && i > start {
+                let space = (start + 1..=i).rev().find(|k| chars[k - 1].is_whitespace());
+                start = match (self.wrap, space) {
+                    (Wrap::Word, Some(k)) => k,
+                    _ => i,
+                };
+                rows.push(start);
+                column = 0;
+                i = start;
+                continue;
+            }
+            column += w;
+            i += 1;
+        }
+        rows
+    }
+    /// display column of char `x` in the row starting at char `start` of line `y`.
+    fn row_column(&self, y: usize, start: usize, x: usize) -> usize {
+        let mut column = 0;
+        for c in self.buffer.line(y).chars().skip(start).take(x - start) {
+            column += self.char_width(c, column);
+        }
+        column
+    }
+    /// row of the cursor in the wrapped line, and its column in the row.
+    /// At the end of a full row the cursor is on the last column.
+    fn cursor_row(&self) -> (usize, usize) {
+        let rows = self.line_rows(self.cur_y);
+        let row = rows
+            .iter()
+            .rposition(|start| *start <= self.cur_x)
+            .unwrap_or(0);
+        let column = self.row_column(self.cur_y, rows[row], self.cur_x);
+        let width = self.window.text_width() as usize;
+        (row, column.min(width.saturating_sub(1)))
+    }
+    /// display rows from the top of the window to `row` of line `y`, which is not above the top.
+    fn rows_from_top(&self, y: usize, row: usize) -> usize {
+        if y == self.begin {
+            return row.saturating_sub(self.begin_row);
+        }
+        let first = self
+            .line_rows(self.begin)
+            .len()
+            .saturating_sub(self.begin_row);
+        first
+            + (self.begin + 1..y)
+                .map(|y| self.line_rows(y).len())
+                .sum::<usize>()
+            + row
+    }
+    /// adjust `begin` and `begin_row` so that `row` of the cursor line is in the window.
+    fn scroll_to_row(&mut self, row: usize) {
+        let height = self.window.height() as usize;
+        self.begin_row = self
+            .begin_row
+            .min(self.line_rows(self.begin).len().saturating_sub(1));
+        if (self.cur_y, row) < (self.begin, self.begin_row) {
+            self.begin = self.cur_y;
+            self.begin_row = row;
+            return;
+        }
+        if height > 0 && self.cur_y >= self.begin + height {
+            // a line has one row at least.
+            self.begin = self.cur_y + 1 - height;
+            self.begin_row = 0;
+        }
+        let rows = self.rows_from_top(self.cur_y, row);
+        if height > 0 && rows >= height {
+            self.move_top(rows + 1 - height, true);
+        }
+    }
+    /// move the top of the window by `n` display rows. Returns false at the end of the text.
+    fn move_top(&mut self, n: usize, down: bool) -> bool {
+        for _ in 0..n {
+            if down && self.begin_row + 1 < self.line_rows(self.begin).len() {
+                self.begin_row += 1;
+            } else if down && self.begin + 1 < self.buffer.len_lines() {
+                self.begin += 1;
+                self.begin_row = 0;
+            } else if !down && self.begin_row > 0 {
+                self.begin_row -= 1;
+            } else if !down && self.begin > 0 {
+                self.begin -= 1;
+                self.begin_row = self.line_rows(self.begin).len() - 1;
+            } else {
+                return false;
+            }
+        }
+        true
+    }
+    /// move the cursor a display row down or up, keeping the column.
+    /// Returns false at the end of the text.
+    fn move_row(&mut self, down: bool) -> bool {
+        let (row, column) = self.cursor_row();
+        let rows = self.line_rows(self.cur_y);
+        let (y, row) = if down && row + 1 < rows.len() {
+            (self.cur_y, row + 1)
+        } else if down && self.cur_y + 1 < self.buffer.len_lines() {
+            (self.cur_y + 1, 0)
+        } else if !down && row > 0 {
+            (self.cur_y, row - 1)
+        } else if !down && self.cur_y > 0 {
+            (self.cur_y - 1, self.line_rows(self.cur_y - 1).len() - 1)
+        } else {
+            return false;
+        };
+        let rows = self.line_rows(y);
+        let start = rows[row];
+        let end = match rows.get(row + 1) {
+            Some(next) => next - 1, // the last char of the row
+            None => self.buffer.line_len(y),
+        };
+        let mut x = start;
+        while x < end && self.row_column(y, start, x + 1) <= column {
+            x += 1;
+        }
+        self.set_cur_y(y);
+        self.cur_x = x;
+        true
+    }
+    /// display the cursor, redrawing the window if it is scrolled.
+    fn show_cursor<W: Write>(&mut self, output: &mut W) {
+        let top = (self.begin, self.begin_row);
+        self.update_win_cur();
+        if top != (self.begin, self.begin_row) {
+            self.redraw(output);
+        } else {
+            self.redraw_cursor(output);
+        }
+    }
     /// return cursor y position on the buffer coodinate.
     pub fn cur_y(&self) -> usize {
         self.cur_y
     }
     /// set cursor y position on the buffer coodinate.
     pub fn set_cur_y(&mut self, y: usize) {
-        if y < self.buffer.len() {
+        if y < self.buffer.len_lines() {
             self.cur_y = y
         }
         self.calc_line();
     }
     pub fn current_line_len(&self) -> usize {
-        self.buffer[self.cur_y].chars().count()
+        self.buffer.line_len(self.cur_y)
     }
+    /// scroll the window `n` lines forward, or display rows if wrapped, with the cursor.
     pub fn scrollup(&mut self, n: usize) {
-        if self.begin < self.buffer.len() - self.window.height() as usize + n {
+        if self.wrap != Wrap::Off {
+            for _ in 0..n {
+                if !self.move_top(1, true) {
+                    break;
+                }
+                self.move_row(true);
+            }
+            self.update_win_cur();
+            return;
+        }
+        if self.begin + (self.window.height() as usize) < self.buffer.len_lines() + n {
             self.begin += n;
             self.set_cur_y(self.cur_y + n);
         }
     }
+    /// scroll the window `n` lines backward, or display rows if wrapped, with the cursor.
     pub fn scrolldown(&mut self, n: usize) {
+        if self.wrap != Wrap::Off {
+            for _ in 0..n {
+                if !self.move_top(1, false) {
+                    break;
+                }
+                self.move_row(false);
+            }
+            self.update_win_cur();
+            return;
+        }
         if self.begin >= n {
             self.begin -= n;
             if self.cur_y >= n {
@@ -146,7 +392,12 @@ impl EditBuffer {
             }
         }
     }
-    pub fn cursor_down(&mut self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
+    pub fn cursor_down<W: Write>(&mut self, output: &mut W) {
+        if self.wrap != Wrap::Off {
+            self.move_row(true);
+            self.show_cursor(output);
+            return;
+        }
         if self.cur_y() >= self.begin + self.window().height() as usize - 1 {
             self.scrollup(1);
             self.redraw(output);
@@ -159,14 +410,22 @@ impl EditBuffer {
     fn calc_line(&mut self) {
         self.cache_size = vec![];
         self.cache_width = vec![];
-        for uni_c in self.buffer[self.cur_y].chars() {
+        let mut column = 0;
+        for uni_c in self.buffer.line(self.cur_y).chars() {
+            let width = self.char_width(uni_c, column);
+            column += width;
             self.cache_size.push(uni_c.len_utf8());
-            self.cache_width.push(uni_c.width().unwrap());
+            self.cache_width.push(width);
         }
         self.cache_width.push(0); // dummy for newline
         self.cache_size.push(0); // dummy for newline
     }
-    pub fn cursor_up(&mut self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
+    pub fn cursor_up<W: Write>(&mut self, output: &mut W) {
+        if self.wrap != Wrap::Off {
+            self.move_row(false);
+            self.show_cursor(output);
+            return;
+        }
         if self.cur_y() > self.begin {
             self.set_cur_y(self.cur_y() - 1);
             self.update_win_cur();
@@ -176,15 +435,21 @@ impl EditBuffer {
             self.redraw(output);
         }
     }
-    pub fn cursor_left(&mut self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
+    pub fn cursor_left<W: Write>(&mut self, output: &mut W) {
+        if self.wrap != Wrap::Off {
+            if self.cur_x > 0 {
+                self.cur_x -= 1;
+            } else if self.cur_y > 0 {
+                self.set_cur_y(self.cur_y - 1);
+                self.cur_x = self.current_line_len();
+            }
+            self.show_cursor(output);
+            return;
+        }
         if self.cur_x() > 0 {
             // move to prev char
             self.set_cur_x(self.cur_x() - 1);
-            let mut cursor_x = 0;
-            for i in 0..self.cur_x {
-                cursor_x += self.cache_width[i];
-            }
-            self.window().set_cur_x(cursor_x as u16);
+            self.update_win_cur();
             self.redraw_cursor(output);
         } else if self.cur_x() == 0 {
             // cursor is top of the line
@@ -206,7 +471,17 @@ impl EditBuffer {
             }
         }
     }
-    pub fn cursor_right(&mut self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
+    pub fn cursor_right<W: Write>(&mut self, output: &mut W) {
+        if self.wrap != Wrap::Off {
+            if self.cur_x < self.current_line_len() {
+                self.cur_x += 1;
+            } else if self.cur_y + 1 < self.buffer.len_lines() {
+                self.set_cur_y(self.cur_y + 1);
+                self.cur_x = 0;
+            }
+            self.show_cursor(output);
+            return;
+        }
         if self.cur_x() >= self.current_line_len() {
             // cursor is end of the line
             if self.window().cur_y() >= self.window().height() - 1 {
@@ -216,7 +491,7 @@ impl EditBuffer {
                 self.update_win_cur();
                 self.redraw(output);
             } else {
-                if self.cur_y < self.buffer.len() - 1 {
+                if self.cur_y + 1 < self.buffer.len_lines() {
                     // goto top of next line
                     self.set_cur_y(self.cur_y() + 1);
                     self.set_cur_x(0);
@@ -227,131 +502,515 @@ impl EditBuffer {
         } else {
             // move to next char
             self.set_cur_x(self.cur_x() + 1);
-            let mut cursor_x = 0;
-            for i in 0..self.cur_x {
-                cursor_x += self.cache_width[i];
-            }
-            self.window().set_cur_x(cursor_x as u16);
+            self.update_win_cur();
             self.redraw_cursor(output);
         }
     }
     pub fn replace_char(&mut self, ch: char) {
         self.set_cur_x(self.cur_x);
         if self.current_line_len() > 0 {
-            let mut line: Vec<char> = self.buffer[self.cur_y].clone().chars().collect();
-            line[self.cur_x] = ch;
-            let mut s = String::new();
-            for c in line {
-                s.push(c)
-            }
-            self.buffer[self.cur_y] = s;
-            self.window.set_cur_x(self.cur_x as u16);
-            self.window.set_cur_y(self.cur_y as u16);
+            let pos = self.buffer.pos(self.cur_x, self.cur_y);
+            let len = if self.cur_x < self.current_line_len() {
+                1
+            } else {
+                0
+            };
+            self.edit(pos, len, &ch.to_string(), (self.cur_x, self.cur_y));
         }
     }
     pub fn insert_char(&mut self, ch: char) {
         self.set_cur_x(self.cur_x);
-        if self.current_line_len() > 0 {
-            // insert char on the existing line
-            let mut line: Vec<char> = self.buffer[self.cur_y].clone().chars().collect();
-            line.insert(self.cur_x, ch);
-            let mut s = String::new();
-            for c in line {
-                s.push(c)
+        let pos = self.buffer.pos(self.cur_x, self.cur_y);
+        self.edit(pos, 0, &ch.to_string(), (self.cur_x + 1, self.cur_y));
+    }
+    pub fn insert_newline(&mut self) {
+        self.set_cur_x(self.cur_x);
+        let pos = self.buffer.pos(self.cur_x, self.cur_y);
+        self.edit(pos, 0, "\n", (0, self.cur_y + 1));
+    }
+    pub fn delete_char(&mut self) {
+        self.set_cur_x(self.cur_x);
+        let pos = self.buffer.pos(self.cur_x, self.cur_y);
+        if pos < self.buffer.len_chars() {
+            // delete char, or delete NEWLINE at the end of line -> join to the next line.
+            self.edit(pos, 1, "", (self.cur_x, self.cur_y));
+        }
+        self.update_win_cur();
+    }
+    /// delete the char before the cursor, or join to the previous line at the beginning of line.
+    pub fn backspace(&mut self) {
+        self.set_cur_x(self.cur_x);
+        let pos = self.cursor_pos();
+        if pos > 0 {
+            let cursor_after = self.buffer.xy(pos - 1);
+            self.edit(pos - 1, 1, "", cursor_after);
+        }
+        self.update_win_cur();
+    }
+    /// replace `len` chars at `pos` by `s` and move the cursor to `cursor_after`.
+    /// Every mutation of the text goes through here to be recorded to the undo history.
+    fn edit(&mut self, pos: usize, len: usize, s: &str, cursor_after: (usize, usize)) {
+        let deleted = self.buffer.slice(pos, pos + len);
+        self.apply(pos, len, s);
+        self.history.record(Edit {
+            pos,
+            deleted,
+            inserted: s.to_string(),
+            cursor_before: (self.cur_x, self.cur_y),
+            cursor_after,
+        });
+        self.cur_x = cursor_after.0;
+        self.cur_y = cursor_after.1;
+        self.scroll_to_cursor();
+    }
+    /// replace `len` chars at `pos` by `s` and keep the mark and the markers on the same text.
+    fn apply(&mut self, pos: usize, len: usize, s: &str) {
+        if !self.markers.is_empty() {
+            let (x, y) = self.buffer.xy(pos);
+            let removed = self.buffer.slice(pos, pos + len).matches('\n').count();
+            self.move_markers(x == 0 && len == 0, y, removed, s.matches('\n').count());
+        }
+        self.buffer.remove(pos, pos + len);
+        self.buffer.insert(pos, s);
+        if let Some(mark) = self.mark {
+            if mark >= pos + len {
+                self.mark = Some(mark - len + s.chars().count());
+            } else if mark > pos {
+                self.mark = Some(pos);
             }
-            self.cur_x += 1;
-            self.buffer[self.cur_y] = s;
-            self.calc_line();
-            let mut cursor_x = 0;
-            for i in 0..self.cur_x {
-                cursor_x += self.cache_width[i];
+        }
+    }
+    /// replace the text between char offsets `start` and `end` by `s`.
+    /// The cursor moves to the end of the inserted text.
+    pub fn replace_range(&mut self, start: usize, end: usize, s: &str) {
+        let (x, y) = self.buffer.xy(start);
+        let cursor_after = match s.rfind('\n') {
+            Some(i) => (s[i + 1..].chars().count(), y + s.matches('\n').count()),
+            None => (x + s.chars().count(), y),
+        };
+        self.edit(start, end - start, s, cursor_after);
+    }
+    /// insert a string, which may contain newlines, at the cursor.
+    pub fn insert_str(&mut self, s: &str) {
+        self.set_cur_x(self.cur_x);
+        let pos = self.cursor_pos();
+        self.replace_range(pos, pos, s);
+    }
+    /// set the mark at the cursor. The region is between the mark and the cursor.
+    pub fn set_mark(&mut self) {
+        self.set_cur_x(self.cur_x);
+        self.mark = Some(self.cursor_pos());
+    }
+    pub fn clear_mark(&mut self) {
+        self.mark = None;
+    }
+    pub fn mark(&self) -> Option<usize> {
+        self.mark
+    }
+    /// move the markers below the line `y`, or from `y` if the text is inserted before it,
+    /// by the lines `removed` and `inserted` after `y`. Markers on removed lines are dropped.
+    fn move_markers(&mut self, before_line: bool, y: usize, removed: usize, inserted: usize) {
+        let first = if before_line { y } else { y + 1 };
+        for (line, marker) in self.markers.split_off(&first) {
+            if line <= y || line > y + removed {
+                self.markers.insert(line + inserted - removed, marker);
             }
-            self.window.set_cur_x(cursor_x as u16);
-            self.window.set_cur_y(self.cur_y as u16);
+        }
+    }
+    /// char offsets (start, end) of the region.
+    pub fn region(&self) -> Option<(usize, usize)> {
+        let mark = self.mark?;
+        let pos = self
+            .buffer
+            .pos(self.cur_x.min(self.current_line_len()), self.cur_y);
+        Some((mark.min(pos), mark.max(pos)))
+    }
+    /// text in the region. The mark is cleared.
+    pub fn copy_region(&mut self) -> Option<String> {
+        let (start, end) = self.region()?;
+        self.mark = None;
+        Some(self.buffer.slice(start, end))
+    }
+    /// delete the region and return the deleted text. The mark is cleared.
+    pub fn kill_region(&mut self) -> Option<String> {
+        let (start, end) = self.region()?;
+        self.mark = None;
+        let killed = self.buffer.slice(start, end);
+        self.replace_range(start, end, "");
+        Some(killed)
+    }
+    /// delete from the cursor to the end of line, or the newline if the cursor is at the end of line.
+    pub fn kill_line(&mut self) -> String {
+        self.set_cur_x(self.cur_x);
+        let pos = self.cursor_pos();
+        let end = if self.cur_x < self.current_line_len() {
+            self.buffer.pos(self.current_line_len(), self.cur_y)
         } else {
-            // insert char on the blank line
-            self.buffer[self.cur_y] = String::new();
-            self.buffer[self.cur_y].push(ch);
-            self.cur_x = 1;
-            self.calc_line();
-            self.window.set_cur_x(self.cur_x as u16);
-            self.window.set_cur_y(self.cur_y as u16);
+            (pos + 1).min(self.buffer.len_chars())
+        };
+        let killed = self.buffer.slice(pos, end);
+        if end > pos {
+            self.replace_range(pos, end, "");
         }
+        killed
     }
-    pub fn insert_newline(&mut self) {
-        if self.current_line_len() > self.cur_x {
-            // insert NEWLINE between existing line.
-            let mut line2 = self.buffer[self.cur_y].clone();
-            let mut line1 = String::new();
-            for _ in 0..self.cur_x {
-                line1.push(line2.remove(0));
+    /// revert the last edit. return false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        if let Some(edit) = self.history.undo() {
+            let len = edit.inserted.chars().count();
+            self.apply(edit.pos, len, &edit.deleted);
+            self.cur_x = edit.cursor_before.0;
+            self.cur_y = edit.cursor_before.1;
+            self.scroll_to_cursor();
+            true
+        } else {
+            false
+        }
+    }
+    /// apply the last undone edit again. return false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        if let Some(edit) = self.history.redo() {
+            let len = edit.deleted.chars().count();
+            self.apply(edit.pos, len, &edit.inserted);
+            self.cur_x = edit.cursor_after.0;
+            self.cur_y = edit.cursor_after.1;
+            self.scroll_to_cursor();
+            true
+        } else {
+            false
+        }
+    }
+    /// highlight every match of `pattern` in redraw. empty string turns off highlighting.
+    pub fn set_highlight(&mut self, pattern: &str) {
+        self.highlight = if pattern.is_empty() {
+            None
+        } else {
+            Regex::new(&regex::escape(pattern)).ok()
+        };
+    }
+    /// highlight every match of the regular expression in redraw.
+    pub fn set_highlight_regex(&mut self, re: Option<Regex>) {
+        self.highlight = re;
+    }
+    /// move the cursor to the next match of `pattern` after `pos`, wrapping around at the end.
+    pub fn search_forward(&mut self, pattern: &str, pos: usize) -> bool {
+        let found = self
+            .buffer
+            .find(pattern, pos)
+            .or_else(|| self.buffer.find(pattern, 0));
+        self.goto_pos(found)
+    }
+    /// move the cursor to the previous match of `pattern` before `pos`, wrapping around at the top.
+    pub fn search_backward(&mut self, pattern: &str, pos: usize) -> bool {
+        let found = self
+            .buffer
+            .rfind(pattern, pos)
+            .or_else(|| self.buffer.rfind(pattern, self.buffer.len_chars()));
+        self.goto_pos(found)
+    }
+    /// regular expression version of `search_forward`.
+    pub fn search_regex_forward(&mut self, re: &Regex, pos: usize) -> bool {
+        let found = self
+            .buffer
+            .find_regex(re, pos)
+            .or_else(|| self.buffer.find_regex(re, 0));
+        self.goto_pos(found.map(|(start, _)| start))
+    }
+    /// regular expression version of `search_backward`.
+    pub fn search_regex_backward(&mut self, re: &Regex, pos: usize) -> bool {
+        let found = self
+            .buffer
+            .rfind_regex(re, pos)
+            .or_else(|| self.buffer.rfind_regex(re, self.buffer.len_chars()));
+        self.goto_pos(found.map(|(start, _)| start))
+    }
+    /// move the cursor to the next match of `re` after `pos` without wrapping around.
+    /// return the char offset of the end of the match.
+    pub fn next_match(&mut self, re: &Regex, pos: usize) -> Option<usize> {
+        let (start, end) = self.buffer.find_regex(re, pos)?;
+        self.goto_pos(Some(start));
+        Some(end)
+    }
+    /// replace the match of `re` at the cursor by `replacement`, expanding `$1`, `${name}`.
+    /// The cursor moves to the end of the replaced text.
+    pub fn replace_match(&mut self, re: &Regex, replacement: &str) -> bool {
+        let line = self.buffer.line(self.cur_y);
+        let start = text::char_to_byte(&line, self.cur_x);
+        if let Some(caps) = re.captures_at(&line, start) {
+            let m = caps.get(0).unwrap();
+            if m.start() == start {
+                let mut s = String::new();
+                caps.expand(replacement, &mut s);
+                let pos = self.cursor_pos();
+                let len = m.as_str().chars().count();
+                self.replace_range(pos, pos + len, &s);
+                return true;
             }
-            self.buffer[self.cur_y] = line1;
-            self.buffer.insert(self.cur_y + 1, line2);
-            self.cur_x = 0;
-            self.cur_y += 1;
-        } else if self.current_line_len() == 0 {
-            // insert NEWLINE on the blank line.
-            self.buffer.insert(self.cur_y + 1, String::from(""));
-            self.cur_x = 0;
-            self.cur_y += 1;
-        } else if self.current_line_len() == self.cur_x {
-            // append NEW line.
-            self.buffer.insert(self.cur_y + 1, String::from(""));
-            self.cur_x = 0;
-            self.cur_y += 1;
         }
-        self.update_win_cur();
+        false
     }
-    pub fn delete_char(&mut self) {
-        if self.current_line_len() > self.cur_x {
-            // delete char between existing line.
-            let mut line: Vec<char> = self.buffer[self.cur_y].clone().chars().collect();
-            line.remove(self.cur_x);
-            let mut line2 = String::new();
-            for c in line {
-                line2.push(c);
+    /// replace every match of `re` after `pos` by `replacement` as one undo step.
+    /// return the number of replacements.
+    pub fn replace_all(&mut self, re: &Regex, replacement: &str, pos: usize) -> usize {
+        let (x, y0) = self.buffer.xy(pos.min(self.buffer.len_chars()));
+        let mut count = 0;
+        let mut changed: Option<(usize, usize)> = None; // first and last replaced line
+        let mut lines = vec![];
+        for y in y0..self.buffer.len_lines() {
+            let line = self.buffer.line(y);
+            let start = if y == y0 {
+                text::char_to_byte(&line, x)
+            } else {
+                0
+            };
+            let mut s = String::new();
+            let mut last = 0;
+            let mut n = 0;
+            // search from `start`, not to miss a match at it overlapped by one before it.
+            let mut at = start;
+            while let Some(caps) = re.captures_at(&line, at) {
+                let m = caps.get(0).unwrap();
+                // an empty match right after the previous match is skipped, as `captures_iter`.
+                if !(m.is_empty() && n > 0 && m.start() == last) {
+                    s.push_str(&line[last..m.start()]);
+                    caps.expand(replacement, &mut s);
+                    last = m.end();
+                    n += 1;
+                }
+                at = match line[m.end()..].chars().next() {
+                    _ if !m.is_empty() => m.end(),
+                    Some(c) => m.end() + c.len_utf8(),
+                    None => break,
+                };
+            }
+            s.push_str(&line[last..]);
+            if n > 0 {
+                count += n;
+                changed = Some((changed.map_or(y, |(first, _)| first), y));
             }
-            self.buffer[self.cur_y] = line2;
-        } else if self.current_line_len() == 0 {
-            // delete blank line.
-            self.buffer.remove(self.cur_y);
-            self.cur_x = 0;
-        } else if self.current_line_len() == self.cur_x {
-            if self.cur_y < self.buffer.len() - 1 {
-                // delete NEWLINE at the end of line -> join to the next line.
-                let mut line1 = String::from(&self.buffer[self.cur_y]);
-                line1.push_str(&self.buffer[self.cur_y + 1]);
-                self.buffer.remove(self.cur_y + 1);
-                self.buffer[self.cur_y] = line1;
+            lines.push(s);
+        }
+        if let Some((first, last)) = changed {
+            let start = self.buffer.line_to_char(first);
+            let end = self.buffer.line_to_char(last) + self.buffer.line_len(last);
+            let s = lines[first - y0..=last - y0].join("\n");
+            self.edit(start, end - start, &s, (self.cur_x, self.cur_y));
+        }
+        count
+    }
+    fn goto_pos(&mut self, pos: Option<usize>) -> bool {
+        if let Some(pos) = pos {
+            let (x, y) = self.buffer.xy(pos);
+            self.goto(x, y);
+            true
+        } else {
+            false
+        }
+    }
+    /// move the cursor to (x, y) and scroll the window to show it.
+    pub fn goto(&mut self, x: usize, y: usize) {
+        self.set_cur_y(y);
+        self.set_cur_x(x);
+        self.scroll_to_cursor();
+    }
+    /// move the cursor to (x, y) and scroll the window to show the line at the center.
+    pub fn goto_center(&mut self, x: usize, y: usize) {
+        self.set_cur_y(y);
+        self.set_cur_x(x);
+        self.begin = self.cur_y.saturating_sub(self.window.height() as usize / 2);
+        self.begin_row = 0;
+        self.update_win_cur();
+    }
+    /// go to "LINE", "LINE:COL", or "+N"/"-N" lines from the cursor, at the center of the
+    /// window. Lines and columns are 1 origin. Returns false if the position is invalid.
+    pub fn goto_position(&mut self, position: &str) -> bool {
+        match parse_position(position, self.cur_y) {
+            Some((x, y)) => {
+                let last = self.buffer.len_lines() - 1;
+                self.goto_center(x, y.min(last));
+                true
             }
+            None => false,
+        }
+    }
+    /// char offset of the cursor.
+    pub fn cursor_pos(&self) -> usize {
+        self.buffer.pos(self.cur_x, self.cur_y)
+    }
+    pub fn line_begin(&mut self) {
+        self.goto(0, self.cur_y);
+    }
+    pub fn line_end(&mut self) {
+        self.goto(self.current_line_len(), self.cur_y);
+    }
+    pub fn buffer_begin(&mut self) {
+        self.goto(0, 0);
+    }
+    pub fn buffer_end(&mut self) {
+        let (x, y) = self.buffer.xy(self.buffer.len_chars());
+        self.goto(x, y);
+    }
+    /// move to the end of the word at or after the cursor.
+    pub fn word_forward(&mut self) {
+        let len = self.buffer.len_chars();
+        let mut pos = self.cursor_pos();
+        while pos < len && !is_word(&self.buffer, pos) {
+            pos += 1;
+        }
+        while pos + 1 < len && same_word(&self.buffer, pos) {
+            pos += 1;
+        }
+        self.goto_pos(Some((pos + 1).min(len)));
+    }
+    /// move to the beginning of the word at or before the cursor.
+    pub fn word_backward(&mut self) {
+        let mut pos = self.cursor_pos();
+        while pos > 0 && !is_word(&self.buffer, pos - 1) {
+            pos -= 1;
+        }
+        while pos > 1 && same_word(&self.buffer, pos - 2) {
+            pos -= 1;
+        }
+        self.goto_pos(Some(pos.saturating_sub(1)));
+    }
+    /// move to the blank line after the paragraph, or the end of the buffer.
+    pub fn paragraph_forward(&mut self) {
+        let last = self.buffer.len_lines() - 1;
+        let blank = |y: usize| self.buffer.line(y).trim().is_empty();
+        let mut y = self.cur_y;
+        while y < last && blank(y) {
+            y += 1;
+        }
+        while y < last && !blank(y) {
+            y += 1;
+        }
+        if blank(y) {
+            self.goto(0, y);
+        } else {
+            self.buffer_end();
+        }
+    }
+    /// move to the blank line before the paragraph, or the beginning of the buffer.
+    pub fn paragraph_backward(&mut self) {
+        let blank = |y: usize| self.buffer.line(y).trim().is_empty();
+        let mut y = self.cur_y;
+        while y > 0 && blank(y) {
+            y -= 1;
+        }
+        while y > 0 && !blank(y) {
+            y -= 1;
+        }
+        self.goto(0, y);
+    }
+    /// scroll one window forward, leaving one line of the previous page, and move the cursor
+    /// as many lines. At the last page the cursor goes to the last line.
+    pub fn page_down(&mut self) {
+        let height = self.window.height() as usize;
+        let step = height.saturating_sub(1).max(1);
+        let last = self.buffer.len_lines() - 1;
+        let max_begin = (last + 1).saturating_sub(height).max(self.begin);
+        self.begin = (self.begin + step).min(max_begin);
+        self.begin_row = 0;
+        self.goto(self.cur_x, (self.cur_y + step).min(last));
+    }
+    /// scroll one window backward and move the cursor as many lines.
+    pub fn page_up(&mut self) {
+        let step = (self.window.height() as usize).saturating_sub(1).max(1);
+        self.begin = self.begin.saturating_sub(step);
+        self.begin_row = 0;
+        self.goto(self.cur_x, self.cur_y.saturating_sub(step));
+    }
+    /// first line displayed in the window.
+    pub fn begin(&self) -> usize {
+        self.begin
+    }
+    pub fn set_begin(&mut self, begin: usize) {
+        if begin < self.buffer.len_lines() {
+            self.begin = begin;
+            self.begin_row = 0;
+        }
+    }
+    /// close the current undo step so that the next edit is undone separately.
+    pub fn undo_boundary(&mut self) {
+        self.history.seal();
+    }
+    /// adjust `begin` so that the cursor line is displayed in the window.
+    pub fn scroll_to_cursor(&mut self) {
+        let height = self.window.height() as usize;
+        if self.cur_y < self.begin {
+            self.begin = self.cur_y;
+            self.begin_row = 0;
+        } else if height > 0 && self.cur_y >= self.begin + height {
+            self.begin = self.cur_y + 1 - height;
+            self.begin_row = 0;
         }
         self.update_win_cur();
     }
     pub fn set_window(&mut self, win: Window) {
         self.window = win;
     }
+    /// replace the window after the terminal was resized, keeping the cursor line visible.
+    pub fn resize(&mut self, win: Window) {
+        self.window = win;
+        self.begin = self.begin.min(self.buffer.len_lines().saturating_sub(1));
+        self.scroll_to_cursor();
+    }
+    /// cursor and scroll position, to be restored by `set_view`.
+    pub fn view(&self) -> View {
+        View {
+            cur_x: self.cur_x,
+            cur_y: self.cur_y,
+            begin: self.begin,
+            begin_row: self.begin_row,
+            left_column: self.left_column,
+        }
+    }
+    /// restore a view. It is clamped to the text, which may have been edited meanwhile.
+    pub fn set_view(&mut self, view: View) {
+        let last = self.buffer.len_lines().saturating_sub(1);
+        self.cur_y = view.cur_y.min(last);
+        self.cur_x = view.cur_x;
+        self.begin = view.begin.min(last);
+        self.begin_row = view.begin_row;
+        self.left_column = view.left_column;
+        self.scroll_to_cursor();
+    }
     pub fn window(&mut self) -> &mut Window {
         &mut self.window
     }
-    pub fn redraw(&mut self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
-        write!(output, "{}", clear::All).unwrap();
-        write!(output, "{}", cursor::Goto(1, 1)).unwrap();
+    /// draw the lines in the window. Other parts of the screen are not touched.
+    pub fn redraw<W: Write>(&mut self, output: &mut W) {
+        self.window.set_left(self.gutter_width());
+        self.numbered_y = self.cur_y;
+        self.drawn_column = self.left_column;
+        let text_width = self.window.text_width() as usize;
+        let text_x = self.window.x() + self.window.left();
+        let rows = self.screen_rows();
         for y in 0..self.window.height() as usize {
-            let line = if self.buffer.len() > self.begin + y {
-                &self.buffer[self.begin + y]
+            let (line_y, start, end) = rows.get(y).copied().unwrap_or((usize::MAX, 0, 0));
+            let line = if line_y < self.buffer.len_lines() {
+                self.buffer.line(line_y)
             } else {
-                ""
+                String::new()
             };
-            let end = if line.len() > self.window.width() as usize {
-                line.char_indices()
-                    .nth(self.window.width() as usize)
-                    .unwrap()
-                    .0
+            let row = self.window.y() + y as u16;
+            let gutter = if start == 0 {
+                self.gutter(line_y)
             } else {
-                line.len()
+                " ".repeat(self.window.left() as usize) // continued row
             };
-            write!(output, "{}{}", cursor::Goto(1, y as u16 + 1), &line[0..end]).unwrap();
+            write!(output, "{}{}", cursor::Goto(self.window.x(), row), gutter).unwrap();
+            let byte = |x: usize| line.char_indices().nth(x).map_or(line.len(), |(i, _)| i);
+            let (column, cut) = self.draw_line(output, line_y, &line, byte(start)..byte(end));
+            write!(output, "{}", " ".repeat(text_width.saturating_sub(column))).unwrap();
+            // indicators of the text cut off
+            if self.left_column > 0 && !line.is_empty() {
+                write!(output, "{}<", cursor::Goto(text_x, row)).unwrap();
+            }
+            if cut {
+                let x = text_x + text_width as u16 - 1;
+                write!(output, "{}>", cursor::Goto(x, row)).unwrap();
+            }
         }
         write!(
             output,
@@ -361,7 +1020,150 @@ impl EditBuffer {
         .unwrap();
         output.flush().unwrap();
     }
-    pub fn redraw_cursor(&self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
+    pub fn line_numbers(&self) -> LineNumbers {
+        self.line_numbers
+    }
+    pub fn set_line_numbers(&mut self, line_numbers: LineNumbers) {
+        self.line_numbers = line_numbers;
+        self.update_win_cur();
+    }
+    /// show `marker` in the gutter of line `y`, or remove it if None.
+    /// Markers move with their lines when lines are inserted or deleted above them.
+    pub fn set_marker(&mut self, y: usize, marker: Option<char>) {
+        match marker {
+            Some(marker) => self.markers.insert(y, marker),
+            None => self.markers.remove(&y),
+        };
+        self.update_win_cur();
+    }
+    pub fn clear_markers(&mut self) {
+        self.markers.clear();
+        self.update_win_cur();
+    }
+    /// columns of the gutter: a marker column, the line number and a space.
+    /// Only the marker column is shown without line numbers, if there are markers.
+    fn gutter_width(&self) -> u16 {
+        match self.line_numbers {
+            LineNumbers::Off if self.markers.is_empty() => 0,
+            LineNumbers::Off => 1,
+            _ => self.buffer.len_lines().to_string().len() as u16 + 2,
+        }
+    }
+    /// line, start and end char index of each row in the window.
+    fn screen_rows(&self) -> Vec<(usize, usize, usize)> {
+        let height = self.window.height() as usize;
+        let mut rows = vec![];
+        let mut skip = self.begin_row;
+        for y in self.begin..self.buffer.len_lines() {
+            let starts = self.line_rows(y);
+            let len = self.buffer.line_len(y);
+            let skip = std::mem::take(&mut skip).min(starts.len() - 1);
+            for (i, start) in starts.iter().enumerate().skip(skip) {
+                if rows.len() == height {
+                    return rows;
+                }
+                rows.push((y, *start, starts.get(i + 1).copied().unwrap_or(len)));
+            }
+        }
+        rows
+    }
+    /// the gutter of line `y`, `Window::left()` columns.
+    fn gutter(&self, y: usize) -> String {
+        let width = self.window.left() as usize;
+        if width == 0 || y >= self.buffer.len_lines() {
+            return " ".repeat(width);
+        }
+        let marker = self.markers.get(&y).copied().unwrap_or(' ');
+        let number = match self.line_numbers {
+            LineNumbers::Off => return marker.to_string(),
+            LineNumbers::Relative if y != self.cur_y => {
+                (y as isize - self.cur_y as isize).abs().to_string()
+            }
+            _ => (y + 1).to_string(),
+        };
+        let gutter = format!("{}{:>2$} ", marker, number, width.saturating_sub(2));
+        gutter.chars().take(width).collect()
+    }
+    /// draw a line, matches of the search pattern are highlighted.
+    /// The region is drawn in reverse video too. The bytes in `range` are drawn from
+    /// `left_column`. Returns the columns drawn, and whether the line is cut off on the right.
+    fn draw_line<W: Write>(
+        &self,
+        output: &mut W,
+        y: usize,
+        line: &str,
+        range: Range<usize>,
+    ) -> (usize, bool) {
+        let mut invert = vec![false; line.len()]; // for each byte
+        if let Some(re) = &self.highlight {
+            for m in re.find_iter(line) {
+                invert[m.range()].iter_mut().for_each(|b| *b = true);
+            }
+        }
+        if let (Some((start, end)), true) = (self.region(), y < self.buffer.len_lines()) {
+            let line_pos = self.buffer.line_to_char(y);
+            for (x, (i, c)) in line.char_indices().enumerate() {
+                if start <= line_pos + x && line_pos + x < end {
+                    invert[i..i + c.len_utf8()]
+                        .iter_mut()
+                        .for_each(|b| *b = true);
+                }
+            }
+        }
+        let mut inverted = false;
+        let mut column = 0; // display column in the line
+        let mut cut = false;
+        let (left, text_width) = (self.left_column, self.window.text_width() as usize);
+        for (i, c) in line[range.clone()].char_indices() {
+            let i = i + range.start;
+            let start = column;
+            column += self.char_width(c, column);
+            if column <= left {
+                continue;
+            }
+            if column - left > text_width {
+                // a wide char is not split.
+                column = start;
+                cut = true;
+                break;
+            }
+            if invert[i] != inverted {
+                inverted = invert[i];
+                if inverted {
+                    write!(output, "{}", style::Invert).unwrap();
+                } else {
+                    write!(output, "{}", style::NoInvert).unwrap();
+                }
+            }
+            if c == '\t' || start < left {
+                // a wide char partly on the left is drawn as spaces.
+                write!(output, "{}", " ".repeat(column - start.max(left))).unwrap();
+            } else {
+                write!(output, "{}", c).unwrap();
+            }
+        }
+        if inverted {
+            write!(output, "{}", style::NoInvert).unwrap();
+        }
+        (column.saturating_sub(left), cut)
+    }
+    pub fn redraw_cursor<W: Write>(&mut self, output: &mut W) {
+        if self.left_column != self.drawn_column {
+            // scrolled horizontally
+            self.redraw(output);
+            return;
+        }
+        if self.line_numbers == LineNumbers::Relative && self.numbered_y != self.cur_y {
+            // the cursor line moved, so the relative numbers change.
+            self.numbered_y = self.cur_y;
+            for (y, (line_y, start, _)) in self.screen_rows().into_iter().enumerate() {
+                if start == 0 {
+                    let row = self.window.y() + y as u16;
+                    let gutter = self.gutter(line_y);
+                    write!(output, "{}{}", cursor::Goto(self.window.x(), row), gutter).unwrap();
+                }
+            }
+        }
         write!(
             output,
             "{}",
@@ -370,7 +1172,7 @@ impl EditBuffer {
         .unwrap();
         output.flush().unwrap();
     }
-    pub fn disp_params(&mut self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
+    pub fn disp_params<W: Write>(&mut self, output: &mut W) {
         write!(output, "{}", cursor::Goto(60, 1)).unwrap();
         let win_x = self.window.screen().width;
         let win_y = self.window.screen().height;
@@ -401,11 +1203,39 @@ impl EditBuffer {
     }
 }
 
+/// (x, y) of a position given to `goto_position`.
+fn parse_position(position: &str, cur_y: usize) -> Option<(usize, usize)> {
+    let (line, column) = match position.trim().split_once(':') {
+        Some((line, column)) => (line, column.parse::<usize>().ok()?),
+        None => (position.trim(), 1),
+    };
+    let y = if let Some(n) = line.strip_prefix('+') {
+        cur_y + n.parse::<usize>().ok()?
+    } else if let Some(n) = line.strip_prefix('-') {
+        cur_y.saturating_sub(n.parse().ok()?)
+    } else {
+        line.parse::<usize>().ok()?.saturating_sub(1)
+    };
+    Some((column.saturating_sub(1), y))
+}
+
+fn is_word(text: &Text, pos: usize) -> bool {
+    !matches!(
+        char_class(text.char(pos)),
+        CharClass::Space | CharClass::Punct | CharClass::FullWidthPunct
+    )
+}
+
+/// the chars at `pos` and `pos + 1` are in the same word. Kanji followed by hiragana,
+/// such as "書く", is one word.
+fn same_word(text: &Text, pos: usize) -> bool {
+    let (c1, c2) = (char_class(text.char(pos)), char_class(text.char(pos + 1)));
+    is_word(text, pos) && (c1 == c2 || (c1, c2) == (CharClass::Kanji, CharClass::Hiragana))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::stdout;
-    use termion::raw::IntoRawMode;
 
     #[test]
     fn load_file_existing_file() {
@@ -439,7 +1269,7 @@ mod tests {
         let window = Window::new(1, 1, 80, 24, screen);
         let mut buf = EditBuffer::new(window);
         buf.new_buffer();
-        buf.buffer[0] = String::from("12345");
+        buf.buffer.set_line(0, "12345");
         buf.set_cur_y(0);
         buf.set_cur_x(5);
         assert_eq!(buf.cur_x, 5);
@@ -454,7 +1284,7 @@ mod tests {
         let window = Window::new(1, 1, 80, 24, screen);
         let mut buf = EditBuffer::new(window);
         buf.new_buffer();
-        buf.buffer[0] = String::from("12345");
+        buf.buffer.set_line(0, "12345");
         buf.set_cur_y(0);
         buf.set_cur_x(6);
         assert_eq!(buf.cur_x, 5); // truncated.
@@ -468,7 +1298,7 @@ mod tests {
         let window = Window::new(1, 1, 80, 24, screen);
         let mut buf = EditBuffer::new(window);
         buf.new_buffer();
-        buf.buffer[0] = String::from("あいうえお");
+        buf.buffer.set_line(0, "あいうえお");
         buf.set_cur_y(0);
         buf.set_cur_x(5);
         assert_eq!(buf.cur_x, 5); // truncated.
@@ -482,7 +1312,7 @@ mod tests {
         let window = Window::new(1, 1, 80, 24, screen);
         let mut buf = EditBuffer::new(window);
         buf.new_buffer();
-        buf.buffer[0] = String::from("あいうえお");
+        buf.buffer.set_line(0, "あいうえお");
         buf.set_cur_y(0);
         buf.set_cur_x(6);
         assert_eq!(buf.cur_x, 5); // truncated.
@@ -497,7 +1327,7 @@ mod tests {
         let window = Window::new(1, 1, 80, 24, screen);
         let mut buf = EditBuffer::new(window);
         buf.new_buffer();
-        buf.buffer[0] = String::from("12345");
+        buf.buffer.set_line(0, "12345");
         buf.set_cur_y(0);
         assert_eq!(buf.current_line_len(), 5); // truncated.
     }
@@ -511,7 +1341,7 @@ mod tests {
         let window = Window::new(1, 1, 80, 24, screen);
         let mut buf = EditBuffer::new(window);
         buf.new_buffer();
-        buf.buffer[0] = String::from("あいうえお");
+        buf.buffer.set_line(0, "あいうえお");
         buf.set_cur_y(0);
         assert_eq!(buf.current_line_len(), 5); // truncated.
     }
@@ -525,13 +1355,13 @@ mod tests {
         let window = Window::new(1, 1, 80, 24, screen);
         let mut buf = EditBuffer::new(window);
         buf.new_buffer();
-        buf.buffer[0] = String::from("🍎🍊🍣❤👉");
+        buf.buffer.set_line(0, "🍎🍊🍣❤👉");
         buf.set_cur_y(0);
         assert_eq!(buf.current_line_len(), 5); // truncated.
     }
     #[test]
     fn cursor_down_1() {
-        let mut stdout = stdout().into_raw_mode().unwrap();
+        let mut output: Vec<u8> = vec![];
         let screen = Screen {
             width: 80,
             height: 25,
@@ -539,17 +1369,17 @@ mod tests {
         let window = Window::new(1, 1, 80, 24, screen);
         let mut buf = EditBuffer::new(window);
         buf.new_buffer();
-        buf.buffer[0] = String::from("12345");
-        buf.buffer.push(String::from(""));
+        buf.buffer.set_line(0, "12345");
+        buf.buffer.push_line("");
         buf.set_cur_y(0);
         buf.set_cur_x(2);
-        buf.cursor_down(&mut stdout);
+        buf.cursor_down(&mut output);
         assert_eq!(buf.cur_x(), 0);
         assert_eq!(buf.cur_y(), 1);
     }
     #[test]
     fn cursor_down_2() {
-        let mut stdout = stdout().into_raw_mode().unwrap();
+        let mut output: Vec<u8> = vec![];
         let screen = Screen {
             width: 80,
             height: 25,
@@ -557,17 +1387,17 @@ mod tests {
         let window = Window::new(1, 1, 80, 24, screen);
         let mut buf = EditBuffer::new(window);
         buf.new_buffer();
-        buf.buffer[0] = String::from("12345");
-        buf.buffer.push(String::from("あいう"));
+        buf.buffer.set_line(0, "12345");
+        buf.buffer.push_line("あいう");
         buf.set_cur_y(0);
         buf.set_cur_x(5);
-        buf.cursor_down(&mut stdout);
+        buf.cursor_down(&mut output);
         assert_eq!(buf.cur_x(), 3);
         assert_eq!(buf.cur_y(), 1);
     }
     #[test]
     fn cursor_down_3() {
-        let mut stdout = stdout().into_raw_mode().unwrap();
+        let mut output: Vec<u8> = vec![];
         let screen = Screen {
             width: 80,
             height: 25,
@@ -575,12 +1405,628 @@ mod tests {
         let window = Window::new(1, 1, 80, 24, screen);
         let mut buf = EditBuffer::new(window);
         buf.new_buffer();
-        buf.buffer[0] = String::from("あいうえお");
-        buf.buffer.push(String::from("1234567890"));
+        buf.buffer.set_line(0, "あいうえお");
+        buf.buffer.push_line("1234567890");
         buf.set_cur_y(0);
         buf.set_cur_x(5);
-        buf.cursor_down(&mut stdout);
+        buf.cursor_down(&mut output);
         assert_eq!(buf.cur_x(), 5);
         assert_eq!(buf.cur_y(), 1);
     }
+    #[test]
+    fn insert_newline_and_delete_char_join() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "あいうえお");
+        buf.set_cur_y(0);
+        buf.set_cur_x(2);
+        buf.insert_newline();
+        assert_eq!(buf.buffer.line(0), "あい");
+        assert_eq!(buf.buffer.line(1), "うえお");
+        assert_eq!(buf.cur_x(), 0);
+        assert_eq!(buf.cur_y(), 1);
+        buf.set_cur_y(0);
+        buf.set_cur_x(2);
+        buf.delete_char();
+        assert_eq!(buf.buffer.len_lines(), 1);
+        assert_eq!(buf.buffer.line(0), "あいうえお");
+    }
+    #[test]
+    fn insert_char_wchar() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "あう");
+        buf.set_cur_y(0);
+        buf.set_cur_x(1);
+        buf.insert_char('い');
+        assert_eq!(buf.buffer.line(0), "あいう");
+        assert_eq!(buf.cur_x(), 2);
+        assert_eq!(buf.window().cur_x(), 4);
+    }
+    #[test]
+    fn undo_redo_delete_join() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "abc");
+        buf.buffer.push_line("def");
+        buf.set_cur_y(0);
+        buf.set_cur_x(3);
+        buf.delete_char();
+        assert_eq!(buf.buffer.line(0), "abcdef");
+        assert!(buf.undo());
+        assert_eq!(buf.buffer.len_lines(), 2);
+        assert_eq!(buf.buffer.line(0), "abc");
+        assert_eq!(buf.buffer.line(1), "def");
+        assert_eq!((buf.cur_x(), buf.cur_y()), (3, 0));
+        assert!(buf.redo());
+        assert_eq!(buf.buffer.line(0), "abcdef");
+        assert!(!buf.redo());
+    }
+    #[test]
+    fn undo_typed_chars_at_once() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.insert_char('あ');
+        buf.insert_char('い');
+        buf.insert_newline();
+        buf.insert_char('う');
+        assert!(buf.undo());
+        assert_eq!(buf.buffer.line(1), "");
+        assert!(buf.undo());
+        assert!(buf.undo());
+        assert_eq!(buf.buffer.to_string(), "");
+        assert_eq!((buf.cur_x(), buf.cur_y()), (0, 0));
+        assert!(!buf.undo());
+    }
+    #[test]
+    fn search_wrap_around() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "日本語のテキスト");
+        buf.buffer.push_line("テキスト検索");
+        buf.set_cur_y(0);
+        buf.set_cur_x(0);
+        assert!(buf.search_forward("テキスト", 0));
+        assert_eq!((buf.cur_x(), buf.cur_y()), (4, 0));
+        assert!(buf.search_forward("テキスト", buf.cursor_pos() + 1));
+        assert_eq!((buf.cur_x(), buf.cur_y()), (0, 1));
+        assert!(buf.search_forward("テキスト", buf.cursor_pos() + 1));
+        assert_eq!((buf.cur_x(), buf.cur_y()), (4, 0));
+        assert!(buf.search_backward("テキスト", buf.cursor_pos()));
+        assert_eq!((buf.cur_x(), buf.cur_y()), (0, 1));
+        assert!(!buf.search_forward("none", 0));
+        assert_eq!((buf.cur_x(), buf.cur_y()), (0, 1));
+    }
+    #[test]
+    fn replace_with_capture_groups() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "name=太郎 age=20");
+        buf.buffer.push_line("keep");
+        buf.buffer.push_line("name=花子");
+        let re = Regex::new("(?P<key>[a-z]+)=(\\S+)").unwrap();
+        assert_eq!(buf.replace_all(&re, "${key}:[$2]", 0), 3);
+        assert_eq!(buf.buffer.line(0), "name:[太郎] age:[20]");
+        assert_eq!(buf.buffer.line(1), "keep");
+        assert_eq!(buf.buffer.line(2), "name:[花子]");
+        assert!(buf.undo());
+        assert_eq!(buf.buffer.line(0), "name=太郎 age=20");
+        assert_eq!(buf.buffer.line(2), "name=花子");
+    }
+    #[test]
+    fn replace_match_overlapped_at_cursor() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "ababa");
+        let re = Regex::new("aba").unwrap();
+        // "aba" at 0 overlaps the one at the cursor.
+        assert_eq!(buf.replace_all(&re, "X", 2), 1);
+        assert_eq!(buf.buffer.line(0), "abX");
+        buf.buffer.set_line(0, "aあb");
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(buf.replace_all(&re, "-", 1), 3);
+        assert_eq!(buf.buffer.line(0), "a-あ-b-");
+    }
+    #[test]
+    fn replace_match_at_cursor() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "あa1b22");
+        let re = Regex::new("[0-9]+").unwrap();
+        assert_eq!(buf.next_match(&re, 0), Some(3));
+        assert!(buf.replace_match(&re, "<$0>"));
+        assert_eq!(buf.buffer.line(0), "あa<1>b22");
+        assert_eq!(buf.cur_x(), 5);
+        assert_eq!(buf.next_match(&re, buf.cursor_pos()), Some(8));
+        assert_eq!(buf.cur_x(), 6);
+        assert!(!buf.replace_match(&Regex::new("x").unwrap(), ""));
+    }
+    #[test]
+    fn kill_region_multi_line() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "あいう");
+        buf.buffer.push_line("abc");
+        buf.buffer.push_line("xyz");
+        buf.goto(1, 0);
+        buf.set_mark();
+        buf.goto(2, 2);
+        assert_eq!(buf.region(), Some((1, 10)));
+        assert_eq!(buf.kill_region(), Some(String::from("いう\nabc\nxy")));
+        assert_eq!(buf.buffer.len_lines(), 1);
+        assert_eq!(buf.buffer.line(0), "あz");
+        assert_eq!((buf.cur_x(), buf.cur_y()), (1, 0));
+        assert_eq!(buf.mark(), None);
+        buf.insert_str("いう\nabc\nxy");
+        assert_eq!(buf.buffer.line(2), "xyz");
+        assert_eq!((buf.cur_x(), buf.cur_y()), (2, 2));
+    }
+    #[test]
+    fn mark_follows_edit() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "abc");
+        buf.goto(2, 0);
+        buf.set_mark();
+        buf.goto(0, 0);
+        buf.insert_char('x');
+        assert_eq!(buf.mark(), Some(3));
+        assert_eq!(buf.copy_region(), Some(String::from("ab")));
+    }
+    #[test]
+    fn kill_line_and_newline() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "abc");
+        buf.buffer.push_line("def");
+        buf.goto(1, 0);
+        assert_eq!(buf.kill_line(), "bc");
+        assert_eq!(buf.kill_line(), "\n");
+        assert_eq!(buf.buffer.line(0), "adef");
+        buf.goto(4, 0);
+        assert_eq!(buf.kill_line(), "");
+    }
+    #[test]
+    fn resize_keeps_cursor_visible() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        for i in 1..30 {
+            buf.buffer.push_line(&i.to_string());
+        }
+        buf.goto(0, 20);
+        assert_eq!(buf.begin(), 0);
+        let screen = Screen {
+            width: 40,
+            height: 10,
+        };
+        buf.resize(screen.layout().editor);
+        assert_eq!(buf.begin(), 13);
+        assert_eq!(buf.window().cur_y(), 7);
+    }
+    #[test]
+    fn redraw_without_terminal() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "abc");
+        buf.buffer.push_line("あいう");
+        let mut output: Vec<u8> = vec![];
+        buf.redraw(&mut output);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("abc"));
+        assert!(output.contains("あいう"));
+    }
+    #[test]
+    fn backspace_joins_lines() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "abc");
+        buf.buffer.push_line("def");
+        buf.goto(0, 1);
+        buf.backspace();
+        assert_eq!(buf.buffer.line(0), "abcdef");
+        assert_eq!((buf.cur_x(), buf.cur_y()), (3, 0));
+        buf.backspace();
+        assert_eq!(buf.buffer.line(0), "abdef");
+        buf.goto(0, 0);
+        buf.backspace();
+        assert_eq!(buf.buffer.line(0), "abdef");
+        assert!(buf.undo());
+        assert_eq!(buf.buffer.line(0), "abcdef");
+    }
+    #[test]
+    fn tab_extends_to_tab_stop() {
+        let screen = Screen {
+            width: 12,
+            height: 3,
+        };
+        let window = Window::new(1, 1, 12, 2, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.set_tab_width(4);
+        buf.buffer.set_line(0, "ab\tc\td\te");
+        buf.goto(3, 0);
+        assert_eq!(buf.window.cur_x(), 4);
+        buf.goto(5, 0);
+        assert_eq!(buf.window.cur_x(), 8);
+        let mut vscreen = VirtualScreen::new(12, 3);
+        buf.redraw(&mut vscreen);
+        // the tab after 'd' does not fit in the window, so the line is cut off.
+        assert_eq!(vscreen.row(1), "ab  c   d  >");
+    }
+    #[test]
+    fn horizontal_scroll_follows_the_cursor() {
+        let screen = Screen {
+            width: 10,
+            height: 3,
+        };
+        let mut buf = EditBuffer::new(Window::new(1, 1, 10, 2, screen));
+        buf.new_buffer();
+        buf.buffer.set_line(0, "0123456789abcdef");
+        buf.buffer.push_line("あいうえおかきくけこ");
+        let mut vscreen = VirtualScreen::new(10, 3);
+        buf.redraw(&mut vscreen);
+        assert_eq!(vscreen.row(1), "012345678>");
+        // a wide char cut by the indicator is erased.
+        assert_eq!(vscreen.row(2), "あいうえ >");
+        buf.goto(16, 0);
+        buf.redraw_cursor(&mut vscreen);
+        assert_eq!(vscreen.row(1), "<89abcdef");
+        assert_eq!(vscreen.row(2), "<おかきく>");
+        assert_eq!(vscreen.cursor(), (10, 1));
+        buf.goto(0, 0);
+        buf.redraw_cursor(&mut vscreen);
+        assert_eq!(vscreen.row(1), "012345678>");
+        assert_eq!(vscreen.cursor(), (1, 1));
+        // the cursor is kept off the indicator on the right.
+        buf.goto(5, 1);
+        buf.redraw_cursor(&mut vscreen);
+        assert_eq!(vscreen.row(1), "<456789ab>");
+        assert_eq!(vscreen.row(2), "<うえおか>");
+        assert_eq!(vscreen.cursor(), (8, 2));
+    }
+    #[test]
+    fn soft_wrap_moves_by_display_rows() {
+        let screen = Screen {
+            width: 10,
+            height: 4,
+        };
+        let mut buf = EditBuffer::new(Window::new(1, 1, 10, 3, screen));
+        buf.new_buffer();
+        buf.buffer.set_line(0, "0123456789abcdef");
+        buf.buffer.push_line("aあいうえおか");
+        buf.buffer.push_line("x");
+        buf.set_wrap(Wrap::Char);
+        // the wide char which does not fit goes to the next row.
+        assert_eq!(buf.line_rows(1), [0, 5]);
+        let mut vscreen = VirtualScreen::new(10, 4);
+        buf.redraw(&mut vscreen);
+        let rows = |vscreen: &VirtualScreen| (1..=3).map(|y| vscreen.row(y)).collect::<Vec<_>>();
+        assert_eq!(rows(&vscreen), ["0123456789", "abcdef", "aあいうえ"]);
+        buf.goto(15, 0);
+        buf.cursor_down(&mut vscreen);
+        assert_eq!((buf.cur_x(), buf.cur_y()), (3, 1)); // same column
+        assert_eq!(vscreen.cursor(), (6, 3));
+        buf.cursor_down(&mut vscreen);
+        assert_eq!((buf.cur_x(), buf.cur_y()), (7, 1));
+        assert_eq!(rows(&vscreen), ["abcdef", "aあいうえ", "おか"]);
+        assert_eq!(vscreen.cursor(), (5, 3));
+        buf.cursor_down(&mut vscreen);
+        assert_eq!((buf.begin, buf.begin_row), (1, 0));
+        assert_eq!(rows(&vscreen), ["aあいうえ", "おか", "x"]);
+        for _ in 0..3 {
+            buf.cursor_up(&mut vscreen);
+        }
+        assert_eq!((buf.cur_x(), buf.cur_y()), (10, 0));
+        assert_eq!((buf.begin, buf.begin_row), (0, 1));
+        assert_eq!(vscreen.cursor(), (1, 1));
+        buf.scrollup(2);
+        assert_eq!((buf.begin, buf.begin_row, buf.cur_y()), (1, 1, 1));
+        buf.scrolldown(1);
+        assert_eq!((buf.begin, buf.begin_row, buf.cur_x()), (1, 0, 0));
+        // continued rows have no line number.
+        buf.set_line_numbers(LineNumbers::Absolute);
+        buf.goto(0, 0);
+        buf.redraw(&mut vscreen);
+        assert_eq!(rows(&vscreen), [" 1 0123456", "   789abcd", "   ef"]);
+    }
+    #[test]
+    fn word_wrap() {
+        let screen = Screen {
+            width: 10,
+            height: 4,
+        };
+        let mut buf = EditBuffer::new(Window::new(1, 1, 10, 3, screen));
+        buf.new_buffer();
+        buf.buffer.set_line(0, "hello world again");
+        buf.set_wrap(Wrap::Word);
+        assert_eq!(buf.line_rows(0), [0, 6, 12]);
+        buf.set_wrap(Wrap::Char);
+        assert_eq!(buf.line_rows(0), [0, 10]);
+        // a word longer than the window is wrapped at a char.
+        buf.buffer.set_line(0, "a 0123456789abc");
+        buf.set_wrap(Wrap::Word);
+        assert_eq!(buf.line_rows(0), [0, 2, 12]);
+    }
+    #[test]
+    fn line_and_buffer_motions() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "abc");
+        buf.buffer.push_line("de");
+        buf.goto(1, 0);
+        buf.line_end();
+        assert_eq!((buf.cur_x(), buf.cur_y()), (3, 0));
+        buf.line_begin();
+        assert_eq!((buf.cur_x(), buf.cur_y()), (0, 0));
+        buf.buffer_end();
+        assert_eq!((buf.cur_x(), buf.cur_y()), (2, 1));
+        buf.buffer_begin();
+        assert_eq!((buf.cur_x(), buf.cur_y()), (0, 0));
+    }
+    #[test]
+    fn word_motions() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "foo_1, bar");
+        buf.buffer.push_line("  日本語を書くカタカナ。");
+        buf.goto(0, 0);
+        buf.word_forward();
+        assert_eq!(buf.cur_x(), 5);
+        buf.word_forward();
+        assert_eq!(buf.cur_x(), 10);
+        // kanji followed by hiragana is one word.
+        buf.word_forward();
+        assert_eq!((buf.cur_x(), buf.cur_y()), (6, 1));
+        buf.word_forward();
+        assert_eq!(buf.cur_x(), 8);
+        // a word ends at a change of the kind of Japanese characters.
+        buf.word_forward();
+        assert_eq!(buf.cur_x(), 12);
+        buf.word_forward();
+        assert_eq!(buf.cur_x(), 13);
+        buf.word_backward();
+        assert_eq!(buf.cur_x(), 8);
+        buf.word_backward();
+        assert_eq!(buf.cur_x(), 6);
+        buf.word_backward();
+        assert_eq!(buf.cur_x(), 2);
+        buf.word_backward();
+        assert_eq!((buf.cur_x(), buf.cur_y()), (7, 0));
+        buf.goto(0, 0);
+        buf.word_backward();
+        assert_eq!((buf.cur_x(), buf.cur_y()), (0, 0));
+    }
+    #[test]
+    fn paragraph_motions() {
+        let screen = Screen {
+            width: 80,
+            height: 25,
+        };
+        let window = Window::new(1, 1, 80, 24, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        for line in ["a", "b", "", "", "c", "", "d"] {
+            buf.buffer.push_line(line);
+        }
+        // the buffer starts with an empty line.
+        buf.goto(0, 1);
+        buf.paragraph_forward();
+        assert_eq!(buf.cur_y(), 3);
+        buf.paragraph_forward();
+        assert_eq!(buf.cur_y(), 6);
+        buf.paragraph_forward();
+        assert_eq!((buf.cur_x(), buf.cur_y()), (1, 7));
+        buf.paragraph_backward();
+        assert_eq!(buf.cur_y(), 6);
+        buf.paragraph_backward();
+        assert_eq!(buf.cur_y(), 4);
+        buf.paragraph_backward();
+        assert_eq!(buf.cur_y(), 0);
+    }
+    #[test]
+    fn page_motions_move_the_cursor() {
+        let screen = Screen {
+            width: 80,
+            height: 6,
+        };
+        let window = Window::new(1, 1, 80, 5, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "0");
+        for i in 1..12 {
+            buf.buffer.push_line(&i.to_string());
+        }
+        buf.goto(0, 1);
+        buf.page_down();
+        assert_eq!((buf.begin(), buf.cur_y()), (4, 5));
+        buf.page_down();
+        assert_eq!((buf.begin(), buf.cur_y()), (7, 9));
+        // the last page is full, and the cursor goes to the last line.
+        buf.page_down();
+        assert_eq!((buf.begin(), buf.cur_y()), (7, 11));
+        assert_eq!(buf.window.cur_y(), 4);
+        buf.page_up();
+        assert_eq!((buf.begin(), buf.cur_y()), (3, 7));
+        buf.page_up();
+        buf.page_up();
+        assert_eq!((buf.begin(), buf.cur_y()), (0, 0));
+    }
+    #[test]
+    fn goto_position_centers_the_line() {
+        let screen = Screen {
+            width: 80,
+            height: 11,
+        };
+        let window = Window::new(1, 1, 80, 10, screen);
+        let mut buf = EditBuffer::new(window);
+        buf.new_buffer();
+        buf.buffer.set_line(0, "1");
+        for i in 2..=100 {
+            buf.buffer.push_line(&format!("line {}", i));
+        }
+        assert!(buf.goto_position("42"));
+        assert_eq!((buf.cur_x(), buf.cur_y(), buf.begin()), (0, 41, 36));
+        assert_eq!(buf.window.cur_y(), 5);
+        assert!(buf.goto_position("50:4"));
+        assert_eq!((buf.cur_x(), buf.cur_y()), (3, 49));
+        assert!(buf.goto_position("+3"));
+        assert_eq!((buf.cur_x(), buf.cur_y()), (0, 52));
+        assert!(buf.goto_position("-10"));
+        assert_eq!(buf.cur_y(), 42);
+        // out of range positions are clamped.
+        assert!(buf.goto_position("3:100"));
+        assert_eq!((buf.cur_x(), buf.cur_y(), buf.begin()), (6, 2, 0));
+        assert!(buf.goto_position("1000"));
+        assert_eq!(buf.cur_y(), 99);
+        assert!(!buf.goto_position("abc"));
+        assert!(!buf.goto_position("3:x"));
+        assert_eq!(buf.cur_y(), 99);
+    }
+    #[test]
+    fn line_number_gutter() {
+        let screen = Screen {
+            width: 20,
+            height: 5,
+        };
+        let mut buf = EditBuffer::new(Window::new(1, 1, 20, 4, screen));
+        buf.new_buffer();
+        buf.buffer.set_line(0, "l1");
+        for i in 2..=12 {
+            buf.buffer.push_line(&format!("l{}", i));
+        }
+        let mut vscreen = VirtualScreen::new(20, 5);
+        buf.set_line_numbers(LineNumbers::Absolute);
+        buf.redraw(&mut vscreen);
+        assert_eq!(vscreen.row(1), "  1 l1");
+        assert_eq!(vscreen.cursor(), (5, 1));
+        buf.set_line_numbers(LineNumbers::Relative);
+        buf.cursor_down(&mut vscreen);
+        buf.cursor_down(&mut vscreen);
+        buf.cursor_right(&mut vscreen);
+        assert_eq!(vscreen.row(1), "  2 l1");
+        assert_eq!(vscreen.row(3), "  3 l3");
+        assert_eq!(vscreen.row(4), "  1 l4");
+        assert_eq!(vscreen.cursor(), (6, 3));
+        buf.set_marker(3, Some('>'));
+        buf.set_line_numbers(LineNumbers::Off);
+        buf.redraw(&mut vscreen);
+        assert_eq!(vscreen.row(1), " l1");
+        assert_eq!(vscreen.row(4), ">l4");
+        assert_eq!(vscreen.cursor(), (3, 3));
+        buf.clear_markers();
+        buf.redraw(&mut vscreen);
+        assert_eq!(vscreen.row(4), "l4");
+    }
+    #[test]
+    fn markers_move_with_lines() {
+        let screen = Screen {
+            width: 20,
+            height: 5,
+        };
+        let mut buf = EditBuffer::new(Window::new(1, 1, 20, 4, screen));
+        buf.new_buffer();
+        buf.insert_str("l1\nl2\nl3\nl4");
+        buf.set_marker(0, Some('a'));
+        buf.set_marker(2, Some('c'));
+        buf.set_marker(3, Some('d'));
+        // a line inserted above "l3"
+        buf.goto(2, 1);
+        buf.insert_str("\nnew");
+        assert_eq!(
+            buf.markers.iter().collect::<Vec<_>>(),
+            [(&0, &'a'), (&3, &'c'), (&4, &'d')]
+        );
+        // text inserted before "l3" moves it
+        buf.goto(0, 3);
+        buf.insert_str("x\n");
+        assert_eq!(buf.markers.keys().collect::<Vec<_>>(), [&0, &4, &5]);
+        // the lines deleted or joined to "l1" lose their markers.
+        let end = buf.text().line_to_char(4);
+        buf.replace_range(2, end, "");
+        assert_eq!(buf.text().to_string(), "l1l3\nl4");
+        assert_eq!(
+            buf.markers.iter().collect::<Vec<_>>(),
+            [(&0, &'a'), (&1, &'d')]
+        );
+    }
 }
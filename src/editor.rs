@@ -1,23 +1,61 @@
-use std::io::{stdin, stdout, Write};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, stdin, stdout, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use termion::event::Key;
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
-use termion::screen::AlternateScreen;
+use termion::screen::IntoAlternateScreen;
 use termion::*;
 
 use std::str;
 
+use regex::Regex;
+use signal_hook::consts::SIGWINCH;
+use signal_hook::iterator::Signals;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::macrocommand::find_command;
 use crate::*;
 
 enum AfterPrompt {
     None,
     SaveFileAs,
     ExitY,
+    QueryReplaceFrom,
+    QueryReplaceTo,
+    QueryReplace,
+    PlayMacro,
+    SaveMacro,
+    GotoLine,
+    FindFile,
+    SwitchBuffer,
+    CloseBufferY,
+    BufferList,
+    ScriptCommand,
+    ScriptPrompt(String), // script function called with the input
+}
+
+/// Input to the editor: a key or a change of the terminal size.
+enum InputEvent {
+    Key(io::Result<Key>),
+    Resize,
+}
+
+/// What to do after a script command.
+enum Flow {
+    Next,
+    Break, // exit the innermost loop
 }
 
 enum EditMode {
     Editor,
     Prompt,
     OneKeyInput,
+    Search,
 }
 
 pub struct Editor {
@@ -28,20 +66,260 @@ pub struct Editor {
     edit_mode: EditMode,
     after_prompt: AfterPrompt,
     changed: bool,
+    search_origin: (usize, usize, usize), // cursor x, y and begin when the search started
+    search_backward: bool,
+    search_regex: bool,
+    last_search: String,
+    replace_regex: Option<Regex>,
+    replace_with: String,
+    replace_count: usize,
+    replace_end: usize, // char offset of the end of the current match
+    kill_ring: KillRing,
+    clipboard: Clipboard,
+    last_yank: Option<(usize, usize)>, // char offsets of the text yanked by the previous command
+    last_kill: bool,                   // previous command was a kill
+    debug_mode: bool,
+    recording: Option<Vec<MacroCommand>>, // keyboard macro being recorded
+    keyboard_macro: Vec<MacroCommand>,    // last recorded keyboard macro
+    registers: HashMap<String, String>,
+    script: ScriptEngine,
+    autosave: Option<Duration>, // interval to save a modified file
+    keymaps: Keymaps,
+    pending_keys: Vec<Key>,         // prefix of a key sequence
+    describing: bool,               // describe the next key sequence instead of running it
+    vi: Option<Vi>,                 // vi-like modal editing, if enabled
+    start_position: Option<String>, // position to go after the file is opened
+    buffers: BufferList,            // all buffers. `buf` is the displayed one
+    windows: Windows,               // split of the edit area. `buf` is on the focused window
 }
 
+/// limit of `repeat` with `until_break`, and of `while`.
+const MAX_REPEAT: usize = 1_000_000;
+
 impl Editor {
-    pub fn new(win: Window, status: StatusBar, prompt: Prompt) -> Self {
+    pub fn new(mut win: Window, status: StatusBar, prompt: Prompt) -> Self {
+        let screen = *win.screen();
+        let windows = Windows::new(edit_area(&win, screen), screen);
         Editor {
             buf: EditBuffer::new(win),
-            status: status,
-            prompt: prompt,
+            status,
+            prompt,
             input: String::from(""),
             edit_mode: EditMode::Editor,
             after_prompt: AfterPrompt::None,
             changed: false,
+            search_origin: (0, 0, 0),
+            search_backward: false,
+            search_regex: false,
+            last_search: String::new(),
+            replace_regex: None,
+            replace_with: String::new(),
+            replace_count: 0,
+            replace_end: 0,
+            kill_ring: KillRing::new(),
+            clipboard: Clipboard::from_env(),
+            last_yank: None,
+            last_kill: false,
+            debug_mode: false,
+            recording: None,
+            keyboard_macro: vec![],
+            registers: HashMap::new(),
+            script: ScriptEngine::new(),
+            autosave: None,
+            keymaps: Keymaps::default(),
+            pending_keys: vec![],
+            describing: false,
+            vi: None,
+            start_position: None,
+            buffers: BufferList::new(),
+            windows,
+        }
+    }
+    /// apply the settings of the config file.
+    pub fn set_config(&mut self, config: &Config) {
+        self.buf.set_tab_width(config.tab_width);
+        self.buf.set_line_numbers(config.line_numbers);
+        self.buf.set_wrap(config.wrap);
+        self.status.set_insert_mode(config.insert_mode);
+        self.status.set_colors(config.colors.clone());
+        self.autosave = Some(Duration::from_secs(config.autosave)).filter(|d| !d.is_zero());
+        self.clipboard = Clipboard::from_config(&config.clipboard);
+        if let Ok(keymaps) = Keymaps::new(&config.keymap) {
+            self.keymaps = keymaps;
+        }
+        self.vi = if config.vi_mode {
+            Some(Vi::new())
+        } else {
+            None
+        };
+        self.status
+            .set_mode_name(self.vi.as_ref().map(|vi| vi.mode().name()));
+    }
+    /// go to the position, such as "42" or "42:7", when the editor starts.
+    pub fn set_start_position(&mut self, position: &str) {
+        self.start_position = Some(position.to_string());
+    }
+    /// open the file in a new buffer, which is displayed by `next_buffer`.
+    /// A file which does not exist is created when saved.
+    pub fn add_buffer(&mut self, file_name: &str) -> Result<usize, String> {
+        let mut buf = EditBuffer::new(self.buf.window().clone());
+        buf.set_tab_width(self.buf.tab_width());
+        buf.set_line_numbers(self.buf.line_numbers());
+        buf.set_wrap(self.buf.wrap());
+        if Path::new(file_name).exists() {
+            buf.load_file(file_name)
+                .map_err(|e| format!("{}:{}", e, file_name))?;
+        } else {
+            buf.new_buffer();
+            buf.set_file_name(file_name);
+        }
+        let index = self.buffers.push(buf, false);
+        self.status
+            .set_buffer_index(self.buffers.current(), self.buffers.len());
+        Ok(index)
+    }
+    /// display the buffer at `index` with its name and modified flag.
+    fn switch_buffer<W: Write>(&mut self, index: usize, output: &mut W) {
+        self.buffers.switch(index, &mut self.buf, &mut self.changed);
+        self.show_buffer(output);
+    }
+    fn show_buffer<W: Write>(&mut self, output: &mut W) {
+        self.update_status();
+        self.buf.redraw(output);
+        self.status.redraw(output);
+    }
+    fn update_status(&mut self) {
+        self.status.set_file_name(buffer_name(&self.buf));
+        self.status.set_changed(self.changed);
+        self.status
+            .set_buffer_index(self.buffers.current(), self.buffers.len());
+    }
+    /// the displayed buffer, its number of lines and the cursor line, to find lines changed.
+    fn lines(&self) -> (usize, usize, usize) {
+        let lines = self.buf.text().len_lines();
+        (self.buffers.current(), lines, self.buf.cur_y())
+    }
+    /// keep the lines shown in the other windows on the buffer,
+    /// when lines are inserted or deleted at the cursor since `lines()` returned `before`.
+    fn shift_other_views(&mut self, before: (usize, usize, usize)) {
+        let (buffer, lines, y) = self.lines();
+        if buffer == before.0 && lines != before.1 {
+            let delta = lines as isize - before.1 as isize;
+            self.windows.lines_changed(buffer, y.min(before.2), delta);
+        }
+    }
+    /// keep the buffer and the view of the focused window, before it loses `buf`.
+    fn save_view(&mut self) {
+        let window = EditWindow {
+            buffer: self.buffers.current(),
+            view: self.buf.view(),
+        };
+        self.windows.set(self.windows.focus(), window);
+    }
+    /// give the rectangles of the focused window to `buf` and the status bar.
+    fn apply_layout(&mut self) {
+        let focus = self.windows.focus();
+        self.buf.resize(self.windows.text_window(focus));
+        self.status.set_window(self.windows.status_window(focus));
+    }
+    /// display the buffer and the view of the focused window on `buf`.
+    fn show_window<W: Write>(&mut self, output: &mut W) {
+        let window = self.windows.get(self.windows.focus());
+        self.buffers
+            .switch(window.buffer, &mut self.buf, &mut self.changed);
+        self.apply_layout();
+        self.buf.set_view(window.view);
+        self.update_status();
+        self.redraw(output);
+    }
+    fn focus_window<W: Write>(&mut self, index: usize, output: &mut W) {
+        self.save_view();
+        self.windows.set_focus(index);
+        self.show_window(output);
+    }
+    /// draw the windows other than the focused one, with their status lines and separators.
+    /// The buffer of each window is drawn on its rectangle with its view, then restored.
+    fn redraw_other_windows<W: Write>(&mut self, output: &mut W) {
+        let list = self.buffers.list(&self.buf, self.changed);
+        for index in 0..self.windows.len() {
+            if index == self.windows.focus() {
+                continue;
+            }
+            let window = self.windows.get(index);
+            let buf = match self.buffers.get_mut(window.buffer) {
+                Some(buf) => buf,
+                None => &mut self.buf,
+            };
+            let (view, win) = (buf.view(), buf.window().clone());
+            buf.set_window(self.windows.text_window(index));
+            buf.set_view(window.view);
+            buf.redraw(output);
+            let shown = buf.view(); // clamped to the text
+            buf.set_window(win);
+            buf.set_view(view);
+            self.windows.set(
+                index,
+                EditWindow {
+                    view: shown,
+                    ..window
+                },
+            );
+            let (name, changed) = &list[window.buffer];
+            let mut status = self.status.clone();
+            status.set_window(self.windows.status_window(index));
+            status.set_file_name(name);
+            status.set_changed(*changed);
+            status.set_buffer_index(window.buffer, list.len());
+            status.redraw(output);
+        }
+        for separator in self.windows.layout().1 {
+            for y in separator.y..separator.y + separator.height {
+                write!(output, "{}|", cursor::Goto(separator.x, y)).unwrap();
+            }
         }
     }
+    /// show the buffer list on the edit window. A digit key switches to the buffer.
+    fn list_buffers<W: Write>(&mut self, output: &mut W) {
+        let window = self.buf.window().clone();
+        let list = self.buffers.list(&self.buf, self.changed);
+        for y in 0..window.height() {
+            let line = match list.get(y as usize) {
+                Some((name, changed)) => format!(
+                    "{}{} {} {}",
+                    if y as usize == self.buffers.current() {
+                        '>'
+                    } else {
+                        ' '
+                    },
+                    y + 1,
+                    if *changed { '*' } else { ' ' },
+                    name
+                ),
+                None => String::new(),
+            };
+            let mut row = String::new();
+            for c in line.chars() {
+                if row.width() + c.width().unwrap_or(0) > window.width() as usize {
+                    break;
+                }
+                row.push(c);
+            }
+            let padding = (window.width() as usize).saturating_sub(row.width());
+            write!(
+                output,
+                "{}{}{}",
+                cursor::Goto(window.x(), window.y() + y),
+                row,
+                " ".repeat(padding)
+            )
+            .unwrap();
+        }
+        self.edit_mode = EditMode::OneKeyInput;
+        self.prompt
+            .set_prompt("Buffer number (other keys return): ");
+        self.after_prompt = AfterPrompt::BufferList;
+        self.prompt.redraw(output);
+    }
     pub fn run_editor_with_new_buffer(&mut self, debug_mode: bool) {
         eprintln!("run_editor_with_new_buffer");
         self.buf.new_buffer();
@@ -61,7 +339,7 @@ impl Editor {
         self.status.set_file_name(file_name);
         self.run_editor(debug_mode);
     }
-    pub fn focus_edit_window(&mut self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
+    pub fn focus_edit_window<W: Write>(&mut self, output: &mut W) {
         write!(
             output,
             "{}",
@@ -70,230 +348,1825 @@ impl Editor {
         .unwrap();
         output.flush().unwrap();
     }
+    fn start_search<W: Write>(&mut self, backward: bool, regex: bool, output: &mut W) {
+        self.edit_mode = EditMode::Search;
+        self.search_backward = backward;
+        self.search_regex = regex;
+        self.search_origin = (self.buf.cur_x(), self.buf.cur_y(), self.buf.begin());
+        self.prompt.clear(output);
+        self.show_search(true, output);
+    }
+    /// the pattern in the prompt as a regular expression. literal search pattern is escaped.
+    fn search_pattern(&mut self) -> Option<Regex> {
+        let pattern = String::from(self.prompt.result());
+        if pattern.is_empty() {
+            None
+        } else if self.search_regex {
+            Regex::new(&pattern).ok()
+        } else {
+            Regex::new(&regex::escape(&pattern)).ok()
+        }
+    }
+    /// search the pattern in the prompt incrementally from where the search started.
+    fn search_from_origin<W: Write>(&mut self, output: &mut W) {
+        let (x, y, begin) = self.search_origin;
+        self.buf.set_begin(begin);
+        self.buf.goto(x, y);
+        let origin = self.buf.cursor_pos();
+        let found = match self.search_pattern() {
+            None => self.prompt.result().is_empty(),
+            Some(re) if self.search_backward => self.buf.search_regex_backward(&re, origin + 1),
+            Some(re) => self.buf.search_regex_forward(&re, origin),
+        };
+        self.show_search(found, output);
+    }
+    /// go to the next(or previous) match. Empty pattern reuses the last search.
+    fn search_next<W: Write>(&mut self, backward: bool, output: &mut W) {
+        if self.prompt.result().is_empty() {
+            for c in self.last_search.clone().chars() {
+                self.prompt.push(c);
+            }
+        }
+        self.search_backward = backward;
+        let pos = self.buf.cursor_pos();
+        let found = match self.search_pattern() {
+            None => false,
+            Some(re) if backward => self.buf.search_regex_backward(&re, pos),
+            Some(re) => self.buf.search_regex_forward(&re, pos + 1),
+        };
+        self.show_search(found, output);
+    }
+    fn show_search<W: Write>(&mut self, found: bool, output: &mut W) {
+        let mut prompt = String::from(if found { "" } else { "Failing " });
+        prompt.push_str(if self.search_regex {
+            "Regexp I-search"
+        } else {
+            "I-search"
+        });
+        prompt.push_str(if self.search_backward {
+            " backward: "
+        } else {
+            ": "
+        });
+        self.prompt.set_prompt(&prompt);
+        let re = self.search_pattern();
+        self.buf.set_highlight_regex(re);
+        self.buf.redraw(output);
+        self.status.redraw(output);
+        self.prompt.redraw(output);
+        self.focus_edit_window(output);
+    }
+    fn end_search<W: Write>(&mut self, output: &mut W) {
+        if !self.prompt.result().is_empty() {
+            self.last_search = String::from(self.prompt.result());
+        }
+        self.edit_mode = EditMode::Editor;
+        self.buf.set_highlight("");
+        self.prompt.set_prompt("");
+        self.prompt.clear(output);
+        self.buf.redraw(output);
+        self.status.redraw(output);
+        self.focus_edit_window(output);
+    }
+    /// the regular expression to be replaced is entered.
+    fn query_replace_from<W: Write>(&mut self, output: &mut W) {
+        match Regex::new(&self.input) {
+            Ok(re) => {
+                self.replace_regex = Some(re);
+                self.edit_mode = EditMode::Prompt;
+                self.prompt
+                    .set_prompt(&format!("Query replace {} with: ", self.input));
+                self.after_prompt = AfterPrompt::QueryReplaceTo;
+                self.prompt.redraw(output);
+            }
+            Err(_) => {
+                self.prompt
+                    .set_prompt(&format!("Invalid regexp: {}", self.input));
+                self.prompt.redraw(output);
+                self.focus_edit_window(output);
+            }
+        }
+    }
+    /// the replacement is entered, start asking at each match.
+    fn query_replace_to<W: Write>(&mut self, output: &mut W) {
+        self.replace_with = self.input.clone();
+        self.replace_count = 0;
+        self.prompt.set_prompt(&format!(
+            "Query replacing {} with {}: (y/n/!/q)",
+            self.replace_regex.as_ref().unwrap().as_str(),
+            self.replace_with
+        ));
+        let pos = self.buf.cursor_pos();
+        self.query_replace_next(pos, output);
+    }
+    /// ask for the next match after `pos`.
+    fn query_replace_next<W: Write>(&mut self, pos: usize, output: &mut W) {
+        let re = self.replace_regex.clone().unwrap();
+        match self.buf.next_match(&re, pos) {
+            Some(end) => {
+                self.replace_end = end;
+                self.edit_mode = EditMode::OneKeyInput;
+                self.after_prompt = AfterPrompt::QueryReplace;
+                self.buf.set_highlight_regex(Some(re));
+                self.buf.redraw(output);
+                self.prompt.redraw(output);
+                self.focus_edit_window(output);
+            }
+            None => self.query_replace_finish(output),
+        }
+    }
+    fn query_replace_key<W: Write>(&mut self, c: char, output: &mut W) {
+        let re = self.replace_regex.clone().unwrap();
+        let pos = self.buf.cursor_pos();
+        let empty_match = self.replace_end == pos;
+        match c {
+            'y' | ' ' => {
+                self.buf.replace_match(&re, &self.replace_with);
+                self.replace_count += 1;
+                let pos = self.buf.cursor_pos();
+                self.query_replace_next(if empty_match { pos + 1 } else { pos }, output);
+            }
+            'n' => {
+                let end = self.replace_end;
+                self.query_replace_next(if empty_match { end + 1 } else { end }, output);
+            }
+            '!' => {
+                self.replace_count += self.buf.replace_all(&re, &self.replace_with, pos);
+                self.query_replace_finish(output);
+            }
+            'q' | '\n' => {
+                self.query_replace_finish(output);
+            }
+            _ => {}
+        }
+    }
+    fn query_replace_finish<W: Write>(&mut self, output: &mut W) {
+        self.edit_mode = EditMode::Editor;
+        self.after_prompt = AfterPrompt::None;
+        self.buf.set_highlight("");
+        if self.replace_count > 0 {
+            self.changed = true;
+            self.status.set_changed(self.changed);
+        }
+        self.prompt.clear(output);
+        self.prompt
+            .set_prompt(&format!("Replaced {} occurrence(s)", self.replace_count));
+        self.buf.redraw(output);
+        self.status.redraw(output);
+        self.prompt.redraw(output);
+        self.focus_edit_window(output);
+    }
+    /// add the killed text to the kill ring and copy it to the clipboard.
+    fn kill<W: Write>(&mut self, text: &str, output: &mut W) {
+        if self.last_kill {
+            self.kill_ring.append(text);
+        } else {
+            self.kill_ring.push(text);
+        }
+        self.last_kill = true;
+        if let Some(text) = self.kill_ring.yank() {
+            self.clipboard.copy(text, output);
+        }
+    }
+    fn kill_region<W: Write>(&mut self, output: &mut W) {
+        if let Some(text) = self.buf.kill_region() {
+            self.kill(&text, output);
+        }
+    }
+    fn copy_region<W: Write>(&mut self, output: &mut W) {
+        if let Some(text) = self.buf.copy_region() {
+            self.kill(&text, output);
+        }
+    }
+    fn kill_line<W: Write>(&mut self, output: &mut W) {
+        let text = self.buf.kill_line();
+        self.kill(&text, output);
+    }
+    /// insert the newest killed text. Text copied by other programs is taken from the clipboard.
+    fn yank(&mut self) {
+        if let Some(text) = self.clipboard.paste() {
+            if !text.is_empty() && self.kill_ring.yank() != Some(text.as_str()) {
+                self.kill_ring.push(&text);
+            }
+        }
+        if let Some(text) = self.kill_ring.yank().map(String::from) {
+            let start = self.buf.cursor_pos();
+            self.buf.insert_str(&text);
+            self.last_yank = Some((start, self.buf.cursor_pos()));
+        }
+    }
+    /// replace the text just yanked by the older one in the kill ring.
+    fn yank_pop(&mut self) -> bool {
+        if let Some((start, end)) = self.last_yank {
+            if let Some(text) = self.kill_ring.yank_pop().map(String::from) {
+                self.buf.replace_range(start, end, &text);
+                self.last_yank = Some((start, self.buf.cursor_pos()));
+                return true;
+            }
+        }
+        false
+    }
+    fn set_changed(&mut self, changed: bool) {
+        self.changed = changed;
+        self.status.set_changed(changed);
+    }
+    fn message<W: Write>(&mut self, message: &str, output: &mut W) {
+        self.prompt.set_prompt(message);
+        self.prompt.redraw(output);
+    }
+    /// load `init.rhai` in the config directory, if it exists.
+    fn load_init_script<W: Write>(&mut self, output: &mut W) {
+        if let Some(file_name) = ScriptEngine::init_file().filter(|f| f.exists()) {
+            let result = self
+                .script
+                .load_file(&file_name.to_string_lossy(), &mut self.buf);
+            if let Err(e) = self.script_done(result, output) {
+                self.message(&e, output);
+            }
+        }
+    }
+    /// apply what a script did to the editor: changed flag, message and prompt.
+    fn script_done<W: Write>(
+        &mut self,
+        result: Result<ScriptOutput, String>,
+        output: &mut W,
+    ) -> Result<(), String> {
+        let script_output = result?;
+        if script_output.changed {
+            self.set_changed(true);
+        }
+        for (keys, function) in script_output.bindings {
+            self.keymaps
+                .editor
+                .bind(keys, &format!("script:{}", function));
+        }
+        self.buf.redraw(output);
+        if let Some(message) = script_output.message {
+            self.message(&message, output);
+        }
+        if let Some((label, callback)) = script_output.prompt {
+            self.edit_mode = EditMode::Prompt;
+            self.prompt.set_prompt(&label);
+            self.after_prompt = AfterPrompt::ScriptPrompt(callback);
+            self.prompt.redraw(output);
+        }
+        Ok(())
+    }
+    /// execute an editing command in `COMMANDS`. Shared by keys, scripts and keyboard macros.
+    /// `last_yank` and `last_kill` are left by the previous command.
+    fn execute<W: Write>(
+        &mut self,
+        cmd: &MacroCommand,
+        last_yank: Option<(usize, usize)>,
+        last_kill: bool,
+        output: &mut W,
+    ) -> Result<(), String> {
+        if find_command(&cmd.name).is_none() {
+            return Err(String::from("unknown command"));
+        }
+        let lines = self.lines();
+        let result = self.execute_count(cmd, last_yank, last_kill, output);
+        if self.windows.len() > 1 {
+            // other windows may show the same buffer.
+            self.shift_other_views(lines);
+            self.redraw_other_windows(output);
+            self.buf.redraw_cursor(output);
+        }
+        result
+    }
+    /// execute the command `count()` times.
+    fn execute_count<W: Write>(
+        &mut self,
+        cmd: &MacroCommand,
+        last_yank: Option<(usize, usize)>,
+        last_kill: bool,
+        output: &mut W,
+    ) -> Result<(), String> {
+        self.execute_once(cmd, last_yank, last_kill, output)?;
+        for _ in 1..cmd.count() {
+            let last_yank = self.last_yank.take();
+            let last_kill = std::mem::replace(&mut self.last_kill, false);
+            self.execute_once(cmd, last_yank, last_kill, output)?;
+        }
+        Ok(())
+    }
+    fn execute_once<W: Write>(
+        &mut self,
+        cmd: &MacroCommand,
+        last_yank: Option<(usize, usize)>,
+        last_kill: bool,
+        output: &mut W,
+    ) -> Result<(), String> {
+        match cmd.name.as_str() {
+            "new_buffer" => {
+                self.buf.new_buffer();
+                self.status.set_file_name("[NEW FILE]");
+                self.set_changed(false);
+                self.buf.redraw(output);
+            }
+            "set_file_name" => {
+                self.buf.set_file_name(&cmd.argstr);
+                self.status.set_file_name(&cmd.argstr);
+            }
+            "open_file" => {
+                if let Err(e) = self.buf.load_file(&cmd.argstr) {
+                    return Err(format!("{}:{}", e, cmd.argstr));
+                }
+                self.status.set_file_name(&cmd.argstr);
+                self.set_changed(false);
+                self.buf.redraw(output);
+            }
+            "save_file_as" => {
+                if let Err(e) = self.buf.save_file_as(&cmd.argstr) {
+                    return Err(format!("{}:{}", e, cmd.argstr));
+                }
+                self.status.set_file_name(&cmd.argstr);
+                self.set_changed(false);
+            }
+            "save_file" => {
+                self.buf.save_file()?;
+                self.set_changed(false);
+            }
+            "find_file" => {
+                let index = match self.buffers.find(&cmd.argstr, &self.buf) {
+                    Some(index) => index,
+                    None => self.add_buffer(&cmd.argstr)?,
+                };
+                self.switch_buffer(index, output);
+            }
+            "next_buffer" => {
+                let index = (self.buffers.current() + 1) % self.buffers.len();
+                self.switch_buffer(index, output);
+            }
+            "previous_buffer" => {
+                let len = self.buffers.len();
+                let index = (self.buffers.current() + len - 1) % len;
+                self.switch_buffer(index, output);
+            }
+            "switch_buffer" => {
+                let index = self
+                    .buffers
+                    .find(&cmd.argstr, &self.buf)
+                    .ok_or(format!("no buffer:{}", cmd.argstr))?;
+                self.switch_buffer(index, output);
+            }
+            "close_buffer" => {
+                let closed = self.buffers.current();
+                if self.buffers.close(&mut self.buf, &mut self.changed) {
+                    self.windows.buffer_removed(closed, self.buffers.current());
+                } else {
+                    // the last buffer is emptied.
+                    self.buf.new_buffer();
+                    self.buf.set_file_name("");
+                    self.changed = false;
+                }
+                self.show_buffer(output);
+            }
+            "split_window" | "split_window_vertically" => {
+                self.save_view();
+                if !self.windows.split(cmd.name == "split_window_vertically") {
+                    return Err(String::from("window too small"));
+                }
+                self.apply_layout();
+                self.redraw(output);
+            }
+            "other_window" => {
+                let index = (self.windows.focus() + 1) % self.windows.len();
+                self.focus_window(index, output);
+            }
+            "delete_window" => {
+                if !self.windows.delete() {
+                    return Err(String::from("only one window"));
+                }
+                self.show_window(output);
+            }
+            "delete_other_windows" => {
+                self.save_view();
+                self.windows.delete_others();
+                self.apply_layout();
+                self.redraw(output);
+            }
+            "enlarge_window"
+            | "shrink_window"
+            | "enlarge_window_horizontally"
+            | "shrink_window_horizontally" => {
+                let vertical = cmd.name.ends_with("horizontally");
+                let delta = if cmd.name.starts_with("enlarge") {
+                    1
+                } else {
+                    -1
+                };
+                if !self.windows.resize(vertical, delta) {
+                    return Err(String::from("cannot resize window"));
+                }
+                self.apply_layout();
+                self.redraw(output);
+            }
+            "cursor_up" => {
+                self.buf.cursor_up(output);
+            }
+            "cursor_down" => {
+                self.buf.cursor_down(output);
+            }
+            "cursor_left" => {
+                self.buf.cursor_left(output);
+            }
+            "cursor_right" => {
+                self.buf.cursor_right(output);
+            }
+            "page_up" => {
+                self.buf.page_up();
+                self.buf.redraw(output);
+            }
+            "page_down" => {
+                self.buf.page_down();
+                self.buf.redraw(output);
+            }
+            "line_begin" => {
+                self.buf.line_begin();
+                self.buf.redraw(output);
+            }
+            "line_end" => {
+                self.buf.line_end();
+                self.buf.redraw(output);
+            }
+            "buffer_begin" => {
+                self.buf.buffer_begin();
+                self.buf.redraw(output);
+            }
+            "buffer_end" => {
+                self.buf.buffer_end();
+                self.buf.redraw(output);
+            }
+            "word_forward" => {
+                self.buf.word_forward();
+                self.buf.redraw(output);
+            }
+            "word_backward" => {
+                self.buf.word_backward();
+                self.buf.redraw(output);
+            }
+            "paragraph_forward" => {
+                self.buf.paragraph_forward();
+                self.buf.redraw(output);
+            }
+            "paragraph_backward" => {
+                self.buf.paragraph_backward();
+                self.buf.redraw(output);
+            }
+            "goto_line" => {
+                if cmd.argstr.is_empty() {
+                    let last = self.buf.text().len_lines().saturating_sub(1);
+                    self.buf.goto_center(0, cmd.arg.saturating_sub(1).min(last));
+                } else if !self.buf.goto_position(&cmd.argstr) {
+                    return Err(format!("invalid position:{}", cmd.argstr));
+                }
+                self.buf.redraw(output);
+            }
+            "insert_char" => {
+                let c = cmd.argstr.chars().next().ok_or("no character in argstr")?;
+                if c == '\n' {
+                    if self.status.insert_mode_flag() {
+                        self.buf.insert_newline();
+                    }
+                } else if self.status.insert_mode_flag() {
+                    self.buf.insert_char(c);
+                } else {
+                    self.buf.replace_char(c);
+                    self.buf.set_cur_x(self.buf.cur_x() + 1);
+                }
+                self.set_changed(true);
+                self.buf.redraw(output);
+            }
+            "insert_string" => {
+                self.buf.insert_str(&cmd.argstr);
+                self.set_changed(true);
+                self.buf.redraw(output);
+            }
+            "newline" => {
+                if self.status.insert_mode_flag() {
+                    self.buf.insert_newline();
+                    self.set_changed(true);
+                }
+                self.buf.redraw(output);
+            }
+            "backspace" => {
+                self.buf.backspace();
+                self.set_changed(true);
+                self.buf.redraw(output);
+            }
+            "toggle_insert_mode" => {
+                self.status.toggle_insert_mode();
+            }
+            "delete_char" => {
+                self.buf.delete_char();
+                self.set_changed(true);
+                self.buf.redraw(output);
+            }
+            "undo" => {
+                if self.buf.undo() {
+                    self.set_changed(true);
+                }
+                self.buf.redraw(output);
+            }
+            "redo" => {
+                if self.buf.redo() {
+                    self.set_changed(true);
+                }
+                self.buf.redraw(output);
+            }
+            "replace_all" => {
+                let re = Regex::new(&cmd.argstr).map_err(|e| format!("invalid regexp:{}", e))?;
+                if self.buf.replace_all(&re, &cmd.argstr2, 0) > 0 {
+                    self.set_changed(true);
+                }
+                self.buf.redraw(output);
+            }
+            "set_mark" => {
+                self.buf.set_mark();
+            }
+            "clear_mark" => {
+                self.buf.clear_mark();
+                self.buf.redraw(output);
+            }
+            "kill_region" => {
+                self.last_kill = last_kill;
+                self.kill_region(output);
+                self.set_changed(true);
+                self.buf.redraw(output);
+            }
+            "copy_region" => {
+                self.last_kill = last_kill;
+                self.copy_region(output);
+                self.buf.redraw(output);
+            }
+            "kill_line" => {
+                self.last_kill = last_kill;
+                self.kill_line(output);
+                self.set_changed(true);
+                self.buf.redraw(output);
+            }
+            "yank" => {
+                self.yank();
+                self.set_changed(true);
+                self.buf.redraw(output);
+            }
+            "yank_pop" => {
+                self.last_yank = last_yank;
+                if !self.yank_pop() {
+                    return Err(String::from("Previous command was not a yank"));
+                }
+                self.buf.redraw(output);
+            }
+            "set_register" => {
+                self.registers
+                    .insert(cmd.argstr.clone(), cmd.argstr2.clone());
+            }
+            "copy_to_register" => {
+                let text = match self.buf.region() {
+                    Some(_) => self.buf.copy_region().unwrap_or_default(),
+                    None => self.buf.text().line(self.buf.cur_y()),
+                };
+                self.registers.insert(cmd.argstr.clone(), text);
+                self.buf.redraw(output);
+            }
+            "insert_register" => {
+                let text = self.registers.get(&cmd.argstr).cloned().unwrap_or_default();
+                self.buf.insert_str(&text);
+                self.set_changed(true);
+                self.buf.redraw(output);
+            }
+            "script" => {
+                let result = self.script.eval(&cmd.argstr, &mut self.buf);
+                self.script_done(result, output)?;
+            }
+            "load_script" => {
+                let result = self.script.load_file(&cmd.argstr, &mut self.buf);
+                self.script_done(result, output)?;
+            }
+            "input_keys" => {
+                let keys = parse_keys(&cmd.argstr).ok_or("invalid keys")?;
+                // the keys are a part of the macro being played, not new input.
+                let recording = self.recording.take();
+                for key in keys {
+                    self.input_key(key, output);
+                }
+                if recording.is_some() {
+                    self.recording = recording;
+                }
+            }
+            _ => return Err(String::from("unknown command")),
+        }
+        Ok(())
+    }
+    /// play the last keyboard macro `times` times. Stops at the first failed command.
+    fn play_macro<W: Write>(&mut self, times: usize, output: &mut W) {
+        let commands = self.keyboard_macro.clone();
+        for _ in 0..times {
+            for cmd in &commands {
+                let last_yank = self.last_yank.take();
+                let last_kill = std::mem::replace(&mut self.last_kill, false);
+                if let Err(e) = self.execute(cmd, last_yank, last_kill, output) {
+                    self.message(&format!("{}:{}", cmd.name, e), output);
+                    return;
+                }
+            }
+        }
+    }
+    /// save the last keyboard macro as a script for `ked -s`.
+    fn save_macro<W: Write>(&mut self, file_name: &str, output: &mut W) {
+        let result = File::create(file_name)
+            .map_err(|e| e.to_string())
+            .and_then(|file| {
+                serde_json::to_writer_pretty(file, &self.keyboard_macro).map_err(|e| e.to_string())
+            });
+        match result {
+            Ok(()) => self.message(&format!("Macro saved: {}", file_name), output),
+            Err(e) => self.message(&format!("Cannot save macro: {}", e), output),
+        }
+    }
+    /// save the modified file, if it has a name.
+    fn autosave<W: Write>(&mut self, output: &mut W) {
+        if !self.changed || self.buf.file_name().is_empty() {
+            return;
+        }
+        match self.buf.save_file().map(|_| ()).map_err(str::to_string) {
+            Ok(()) => {
+                self.set_changed(false);
+                self.message("Auto saved", output);
+                self.status.redraw(output);
+            }
+            Err(e) => self.message(&format!("Auto save failed: {}", e), output),
+        }
+        if let EditMode::Editor = self.edit_mode {
+            self.focus_edit_window(output);
+        }
+    }
+    /// re-layout all windows for the new screen size and redraw everything.
+    pub fn resize<W: Write>(&mut self, screen: Screen, output: &mut W) {
+        let layout = screen.layout();
+        self.windows
+            .set_area(edit_area(&layout.editor, screen), screen);
+        self.apply_layout();
+        self.prompt.set_window(layout.prompt);
+        self.redraw(output);
+    }
+    /// redraw all windows, and put the cursor on the focused one.
+    pub fn redraw<W: Write>(&mut self, output: &mut W) {
+        self.buf.redraw(output);
+        self.redraw_other_windows(output);
+        self.status.redraw(output);
+        self.prompt.redraw(output);
+        if let EditMode::Editor = self.edit_mode {
+            self.focus_edit_window(output);
+        }
+        output.flush().unwrap();
+    }
+    /// keys from stdin and SIGWINCH are merged into one channel.
+    fn input_events() -> mpsc::Receiver<InputEvent> {
+        let (tx, rx) = mpsc::channel();
+        let key_tx = tx.clone();
+        thread::spawn(move || {
+            for c in stdin().keys() {
+                if key_tx.send(InputEvent::Key(c)).is_err() {
+                    break;
+                }
+            }
+        });
+        match Signals::new([SIGWINCH]) {
+            Ok(mut signals) => {
+                thread::spawn(move || {
+                    for _ in signals.forever() {
+                        if tx.send(InputEvent::Resize).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            Err(e) => eprintln!("cannot handle SIGWINCH:{}", e),
+        }
+        rx
+    }
     fn run_editor(&mut self, debug_mode: bool) {
-        let stdin = stdin();
+        self.debug_mode = debug_mode;
         // let mut stdout = stdout().into_raw_mode().unwrap();
-        let mut stdout = AlternateScreen::from(stdout().into_raw_mode().unwrap());
-        write!(stdout, "{}", clear::All).unwrap();
-        write!(stdout, "{}", cursor::Goto(1, 1)).unwrap();
-        stdout.flush().unwrap();
-
-        self.buf.redraw(&mut stdout);
-        self.status.redraw(&mut stdout);
-        write!(
-            stdout,
-            "{}",
-            cursor::Goto(self.buf.window().scr_cur_x(), self.buf.window().scr_cur_y())
-        )
-        .unwrap();
-        write!(stdout, "{}", cursor::Show).unwrap();
-        stdout.flush().unwrap();
-        for c in stdin.keys() {
-            match self.edit_mode {
-                EditMode::Editor => {
-                    match c {
-                        Ok(event::Key::Ctrl('c')) => {
-                            if self.changed == true {
-                                self.edit_mode = EditMode::OneKeyInput;
-                                self.prompt.set_prompt("File is modified. Exit? [Y/n]");
-                                self.after_prompt = AfterPrompt::ExitY;
-                                self.prompt.redraw(&mut stdout);
-                            } else {
-                                break;
-                            }
-                        }
-                        Ok(event::Key::Ctrl('s')) => {
-                            match self.buf.save_file() {
-                                Err("No File Name") => {
-                                    self.edit_mode = EditMode::Prompt;
-                                    self.prompt.set_prompt("File Save As: ");
-                                    self.after_prompt = AfterPrompt::SaveFileAs;
-                                    self.prompt.redraw(&mut stdout);
-                                }
-                                _ => {}
-                            }
-                            self.changed = false;
-                            self.status.set_changed(self.changed);
-                        }
-                        Ok(event::Key::Ctrl('a')) => {
-                            self.edit_mode = EditMode::Prompt;
-                            self.prompt.set_prompt("File Save As: ");
-                            self.after_prompt = AfterPrompt::SaveFileAs;
-                            self.prompt.redraw(&mut stdout);
-                        }
-                        Ok(event::Key::PageDown) => {
-                            self.buf.scrollup(1);
-                            self.buf.redraw(&mut stdout);
-                        }
-                        Ok(event::Key::PageUp) => {
-                            self.buf.scrolldown(1);
-                            self.buf.redraw(&mut stdout);
-                        }
-                        Ok(event::Key::Insert) => {
-                            self.status.toggle_insert_mode();
-                        }
-                        Ok(event::Key::Down) => {
-                            self.buf.cursor_down(&mut stdout);
-                        }
-                        Ok(event::Key::Up) => {
-                            self.buf.cursor_up(&mut stdout);
-                        }
-                        Ok(event::Key::Left) => {
-                            self.buf.cursor_left(&mut stdout);
-                        }
-                        Ok(event::Key::Right) => {
-                            self.buf.cursor_right(&mut stdout);
-                        }
-                        Ok(event::Key::Delete) => {
-                            self.buf.delete_char();
-                            self.buf.redraw(&mut stdout);
-                        }
-                        Ok(event::Key::Char(c)) => {
-                            if c == '\n' {
-                                if self.status.insert_mode_flag() {
-                                    self.buf.insert_newline();
-                                    self.buf.redraw(&mut stdout);
-                                }
-                            } else {
-                                if self.status.insert_mode_flag() {
-                                    self.buf.insert_char(c);
-                                } else {
-                                    self.buf.replace_char(c);
-                                    self.buf.set_cur_y(self.buf.cur_x() + 1);
-                                }
-                                self.buf.redraw(&mut stdout);
-                            }
-                            self.changed = true;
-                            self.status.set_changed(self.changed);
-                        }
-                        _ => {}
+        let mut stdout = stdout()
+            .into_raw_mode()
+            .unwrap()
+            .into_alternate_screen()
+            .unwrap();
+        if let Some(position) = self.start_position.take() {
+            self.buf.goto_position(&position);
+        }
+        self.status.set_file_name(buffer_name(&self.buf));
+        self.redraw(&mut stdout);
+        self.load_init_script(&mut stdout);
+        self.focus_edit_window(&mut stdout);
+        let events = Self::input_events();
+        let mut last_save = Instant::now();
+        loop {
+            let event = match self.autosave {
+                Some(interval) => match events.recv_timeout(interval) {
+                    Ok(event) => Some(event),
+                    Err(mpsc::RecvTimeoutError::Timeout) => None,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                },
+                None => match events.recv() {
+                    Ok(event) => Some(event),
+                    Err(_) => break,
+                },
+            };
+            if let Some(interval) = self.autosave {
+                if last_save.elapsed() >= interval {
+                    self.autosave(&mut stdout);
+                    last_save = Instant::now();
+                }
+            }
+            let event = match event {
+                Some(event) => event,
+                None => continue,
+            };
+            match event {
+                InputEvent::Key(Ok(key)) => {
+                    if !self.input_key(key, &mut stdout) {
+                        break;
                     }
-                    if debug_mode {
-                        self.buf.disp_params(&mut stdout);
+                }
+                InputEvent::Key(Err(e)) => eprintln!("input error:{}", e),
+                InputEvent::Resize => {
+                    if let Ok((width, height)) = terminal_size() {
+                        self.resize(Screen { width, height }, &mut stdout);
+                        stdout.flush().unwrap();
                     }
-                    self.status.redraw(&mut stdout);
-                    self.focus_edit_window(&mut stdout);
                 }
-                EditMode::Prompt => {
-                    match c {
-                        Ok(event::Key::Ctrl('c')) => {
-                            self.edit_mode = EditMode::Editor;
-                        }
-                        Ok(event::Key::Backspace) => {
-                            self.prompt.backspace();
-                            self.prompt.redraw(&mut stdout);
-                        }
-                        Ok(event::Key::Char(c)) => {
-                            if c == '\n' {
-                                self.edit_mode = EditMode::Editor;
-                                self.input = String::from(self.prompt.result());
-                                self.prompt.clear(&mut stdout);
-                                self.status.redraw(&mut stdout);
-                                self.focus_edit_window(&mut stdout);
-                                match &mut self.after_prompt {
-                                    AfterPrompt::SaveFileAs => {
-                                        self.buf.save_file_as(&self.input);
-                                        self.status.set_file_name(self.buf.file_name());
-                                        self.status.redraw(&mut stdout);
-                                    }
-                                    _ => {}
-                                }
-                                self.after_prompt = AfterPrompt::None;
-                            } else {
-                                self.prompt.push(c);
-                                self.prompt.redraw(&mut stdout);
-                                stdout.flush().unwrap();
-                            }
-                        }
-                        _ => {}
+            }
+        }
+        write!(stdout, "{}", cursor::Show).unwrap();
+    }
+    /// process one key. Returns false when the editor should exit.
+    /// Keys are collected until they make a sequence bound in the keymap of the mode.
+    pub fn input_key<W: Write>(&mut self, key: Key, output: &mut W) -> bool {
+        let vi_key = matches!(self.edit_mode, EditMode::Editor)
+            && self.pending_keys.is_empty()
+            && !self.describing;
+        let lines = self.lines();
+        if let (true, Some(vi)) = (vi_key, self.vi.as_mut()) {
+            let action = vi.input(key, &mut self.buf);
+            let mode = vi.mode();
+            if action != ViAction::PassThrough {
+                self.record_keys(&[key]);
+            }
+            match action {
+                ViAction::PassThrough => {}
+                ViAction::Repeat(keys) => {
+                    let recording = self.recording.take();
+                    for key in keys {
+                        self.input_key(key, output);
                     }
-                    stdout.flush().unwrap();
+                    self.recording = recording;
+                    return true;
                 }
-                EditMode::OneKeyInput => match c {
-                    Ok(event::Key::Ctrl('c')) => {
-                        self.edit_mode = EditMode::Editor;
-                        self.prompt.set_prompt("");
-                        self.prompt.clear(&mut stdout);
-                        self.focus_edit_window(&mut stdout);
+                action => {
+                    if action == ViAction::Changed {
+                        self.set_changed(true);
+                    }
+                    self.status.set_mode_name(Some(mode.name()));
+                    self.buf.redraw(output);
+                    self.shift_other_views(lines);
+                    self.redraw_other_windows(output);
+                    self.status.redraw(output);
+                    self.focus_edit_window(output);
+                    return true;
+                }
+            }
+        }
+        self.pending_keys.push(key);
+        let keymap = match self.edit_mode {
+            EditMode::Editor => &self.keymaps.editor,
+            EditMode::Prompt => &self.keymaps.prompt,
+            EditMode::Search => &self.keymaps.search,
+            EditMode::OneKeyInput => &self.keymaps.one_key,
+        };
+        let command = match keymap.lookup(&self.pending_keys) {
+            Lookup::Prefix => {
+                // the prompt line is free only in the editor mode.
+                if let EditMode::Editor = self.edit_mode {
+                    let keys = format!("{}-", keys_name(&self.pending_keys));
+                    self.message(&keys, output);
+                    self.focus_edit_window(output);
+                }
+                return true;
+            }
+            Lookup::Command(command) => Some(command),
+            Lookup::Undefined => None,
+        };
+        let keys = std::mem::take(&mut self.pending_keys);
+        if keys.len() > 1 {
+            if let EditMode::Editor = self.edit_mode {
+                self.message("", output); // the prefix shown
+            }
+        }
+        if self.describing {
+            self.describing = false;
+            let description = match &command {
+                Some(command) => format!("{} runs {}", keys_name(&keys), command),
+                None if keys.len() == 1 && matches!(key, Key::Char(_)) => {
+                    format!("{} runs insert_char", keys_name(&keys))
+                }
+                None => format!("{} is undefined", keys_name(&keys)),
+            };
+            self.message(&description, output);
+            self.focus_edit_window(output);
+            return true;
+        }
+        if command.is_none() && keys.len() > 1 {
+            if let EditMode::Editor = self.edit_mode {
+                self.message(&format!("{} is undefined", keys_name(&keys)), output);
+                self.focus_edit_window(output);
+            }
+            return true;
+        }
+        let recorded = self.records_keys();
+        let running = match self.edit_mode {
+            EditMode::Editor => self.editor_key(command, key, output),
+            EditMode::Search => {
+                self.search_key(command, key, output);
+                true
+            }
+            EditMode::Prompt => {
+                self.prompt_key(command, key, output);
+                true
+            }
+            EditMode::OneKeyInput => self.one_key_input(command, key, output),
+        };
+        // keys entering or input to a mode are recorded, unless a command is recorded for them.
+        if recorded || self.records_keys() {
+            self.record_keys(&keys);
+        }
+        running
+    }
+    /// whether the keys input in the current mode are recorded to the keyboard macro.
+    /// The prompts of commands recorded with the input as `argstr` are not.
+    fn records_keys(&self) -> bool {
+        match self.edit_mode {
+            EditMode::Editor => false,
+            EditMode::Search => true,
+            EditMode::Prompt | EditMode::OneKeyInput => !matches!(
+                self.after_prompt,
+                AfterPrompt::GotoLine
+                    | AfterPrompt::FindFile
+                    | AfterPrompt::SwitchBuffer
+                    | AfterPrompt::CloseBufferY
+                    | AfterPrompt::BufferList
+                    | AfterPrompt::PlayMacro
+                    | AfterPrompt::SaveMacro
+            ),
+        }
+    }
+    /// record the keys to the keyboard macro, appended to the keys recorded last.
+    fn record_keys(&mut self, keys: &[Key]) {
+        let commands = match &mut self.recording {
+            Some(commands) => commands,
+            None => return,
+        };
+        match commands.last_mut() {
+            Some(last) if last.name == "input_keys" => {
+                last.argstr = format!("{} {}", last.argstr, keys_name(keys));
+            }
+            _ => commands.push(MacroCommand::new("input_keys", &keys_name(keys))),
+        }
+    }
+    /// run the command of the editor mode. An unbound printable key inserts the char.
+    fn editor_key<W: Write>(&mut self, command: Option<String>, key: Key, output: &mut W) -> bool {
+        // yank-pop and appending kills continue only the previous command.
+        let last_yank = self.last_yank.take();
+        let last_kill = std::mem::replace(&mut self.last_kill, false);
+        let command = match (command, key) {
+            (Some(command), _) => command,
+            (None, Key::Char(c)) => {
+                let cmd = MacroCommand::new("insert_char", &c.to_string());
+                self.execute_key_command(cmd, last_yank, last_kill, output);
+                String::new()
+            }
+            (None, _) => String::new(),
+        };
+        match command.as_str() {
+            "" => {}
+            "exit" => {
+                if self.changed || self.buffers.any_modified() {
+                    self.edit_mode = EditMode::OneKeyInput;
+                    self.prompt.set_prompt("File is modified. Exit? [Y/n]");
+                    self.after_prompt = AfterPrompt::ExitY;
+                    self.prompt.redraw(output);
+                } else {
+                    return false;
+                }
+            }
+            "save_file" => {
+                match self.buf.save_file() {
+                    Err("No File Name") => {
+                        self.edit_mode = EditMode::Prompt;
+                        self.prompt.set_prompt("File Save As: ");
+                        self.after_prompt = AfterPrompt::SaveFileAs;
+                        self.prompt.redraw(output);
                     }
-                    Ok(event::Key::Char(c)) => match &mut self.after_prompt {
-                        AfterPrompt::ExitY => {
-                            if c == 'y' || c == '\n' {
-                                break;
-                            } else {
-                                self.edit_mode = EditMode::Editor;
-                                self.prompt.set_prompt("");
-                                self.prompt.clear(&mut stdout);
-                                self.focus_edit_window(&mut stdout);
-                            }
+                    Ok(_) => {
+                        if let Some(commands) = &mut self.recording {
+                            commands.push(MacroCommand::new("save_file", ""));
                         }
-                        _ => {}
-                    },
-                    _ => {}
-                },
+                    }
+                    Err(_) => {}
+                }
+                self.changed = false;
+                self.status.set_changed(self.changed);
+            }
+            "save_file_as" => {
+                self.edit_mode = EditMode::Prompt;
+                self.prompt.set_prompt("File Save As: ");
+                self.after_prompt = AfterPrompt::SaveFileAs;
+                self.prompt.redraw(output);
+            }
+            "search_forward" => {
+                self.start_search(false, false, output);
+            }
+            "search_backward" => {
+                self.start_search(true, false, output);
+            }
+            "search_regex_forward" => {
+                self.start_search(false, true, output);
+            }
+            "search_regex_backward" => {
+                self.start_search(true, true, output);
+            }
+            "query_replace" => {
+                self.edit_mode = EditMode::Prompt;
+                self.prompt.set_prompt("Query replace regexp: ");
+                self.after_prompt = AfterPrompt::QueryReplaceFrom;
+                self.prompt.redraw(output);
+            }
+            "script_command" => {
+                self.edit_mode = EditMode::Prompt;
+                self.prompt.set_prompt("Script: ");
+                self.after_prompt = AfterPrompt::ScriptCommand;
+                self.prompt.redraw(output);
+            }
+            "start_macro" => {
+                self.recording = Some(vec![]);
+                self.message("Defining keyboard macro...", output);
+            }
+            "end_or_play_macro" => match self.recording.take() {
+                Some(commands) => {
+                    self.message(
+                        &format!("Keyboard macro defined: {} commands", commands.len()),
+                        output,
+                    );
+                    self.keyboard_macro = commands;
+                }
+                None => self.play_macro(1, output),
+            },
+            "play_macro" => {
+                self.edit_mode = EditMode::Prompt;
+                self.prompt.set_prompt("Play keyboard macro times: ");
+                self.after_prompt = AfterPrompt::PlayMacro;
+                self.prompt.redraw(output);
+            }
+            "find_file" => {
+                self.edit_mode = EditMode::Prompt;
+                self.prompt.set_prompt("Find file: ");
+                self.after_prompt = AfterPrompt::FindFile;
+                self.prompt.redraw(output);
+            }
+            "switch_buffer" => {
+                self.edit_mode = EditMode::Prompt;
+                self.prompt.set_prompt("Switch to buffer: ");
+                self.after_prompt = AfterPrompt::SwitchBuffer;
+                self.prompt.redraw(output);
+            }
+            "close_buffer" if self.changed => {
+                self.edit_mode = EditMode::OneKeyInput;
+                self.prompt.set_prompt("Buffer is modified. Close? [y/N]");
+                self.after_prompt = AfterPrompt::CloseBufferY;
+                self.prompt.redraw(output);
+            }
+            "list_buffers" => {
+                self.list_buffers(output);
+            }
+            "goto_line" => {
+                self.edit_mode = EditMode::Prompt;
+                self.prompt.set_prompt("Goto line (LINE[:COL], +N, -N): ");
+                self.after_prompt = AfterPrompt::GotoLine;
+                self.prompt.redraw(output);
+            }
+            "save_macro" => {
+                self.edit_mode = EditMode::Prompt;
+                self.prompt.set_prompt("Save keyboard macro to: ");
+                self.after_prompt = AfterPrompt::SaveMacro;
+                self.prompt.redraw(output);
+            }
+            "describe_key" => {
+                self.describing = true;
+                self.message("Describe key: ", output);
+            }
+            _ => {
+                let cmd = match command.strip_prefix("script:") {
+                    Some(function) => MacroCommand::new("script", &format!("{}()", function)),
+                    None => MacroCommand::new(&command, ""),
+                };
+                self.execute_key_command(cmd, last_yank, last_kill, output);
             }
         }
-        write!(stdout, "{}", cursor::Show).unwrap();
+        if self.buf.mark().is_some() || last_yank.is_some() || self.last_kill {
+            // region or killed/yanked text is changed.
+            self.buf.redraw(output);
+        }
+        if self.debug_mode {
+            self.buf.disp_params(output);
+        }
+        self.status.redraw(output);
+        if let EditMode::Editor = self.edit_mode {
+            self.focus_edit_window(output);
+        }
+        true
     }
-    pub fn run_script(&mut self, script: &Vec<MacroCommand>) {
-        // let mut stdout = stdout().into_raw_mode().unwrap();
-        let mut stdout = AlternateScreen::from(stdout().into_raw_mode().unwrap());
-        write!(stdout, "{}", clear::All).unwrap();
-        write!(stdout, "{}", cursor::Goto(1, 1)).unwrap();
-        stdout.flush().unwrap();
-
-        self.buf.redraw(&mut stdout);
-        self.status.redraw(&mut stdout);
-
-        for cmd in script {
-            match cmd.name.as_str() {
-                "new_buffer" => {
-                    self.buf.new_buffer();
-                    self.status.set_file_name("[NEW FILE]");
+    /// execute a command of `COMMANDS` invoked by a key, and record it to the keyboard macro.
+    fn execute_key_command<W: Write>(
+        &mut self,
+        cmd: MacroCommand,
+        last_yank: Option<(usize, usize)>,
+        last_kill: bool,
+        output: &mut W,
+    ) {
+        match self.execute(&cmd, last_yank, last_kill, output) {
+            Ok(()) => {
+                if cmd.name == "set_mark" {
+                    self.message("Mark set", output);
                 }
-                "set_file_name" => {
-                    self.buf.set_file_name(&cmd.argstr);
-                    self.status.set_file_name(&cmd.argstr);
+                if let Some(commands) = &mut self.recording {
+                    commands.push(cmd);
                 }
-                "open_file" => {
-                    self.buf.load_file(&cmd.argstr).unwrap();
-                    self.status.set_file_name(&cmd.argstr);
+            }
+            Err(e) => self.message(&e, output),
+        }
+    }
+    /// run the command of the incremental search. An unbound printable key extends the pattern.
+    fn search_key<W: Write>(&mut self, command: Option<String>, key: Key, output: &mut W) {
+        match (command.as_deref(), key) {
+            (Some("cancel"), _) => {
+                // cancel: restore the cursor and the scroll position.
+                let (x, y, begin) = self.search_origin;
+                self.buf.set_begin(begin);
+                self.buf.goto(x, y);
+                self.end_search(output);
+            }
+            (Some("search_forward"), _) => {
+                self.search_next(false, output);
+            }
+            (Some("search_backward"), _) => {
+                self.search_next(true, output);
+            }
+            (Some("backspace"), _) => {
+                self.prompt.backspace();
+                self.search_from_origin(output);
+            }
+            (Some("accept"), _) => {
+                self.end_search(output);
+            }
+            (None, Key::Char(c)) => {
+                self.prompt.push(c);
+                self.search_from_origin(output);
+            }
+            _ => {}
+        }
+    }
+    /// run the command of the prompt. An unbound printable key is input.
+    fn prompt_key<W: Write>(&mut self, command: Option<String>, key: Key, output: &mut W) {
+        match (command.as_deref(), key) {
+            (Some("cancel"), _) => {
+                self.edit_mode = EditMode::Editor;
+            }
+            (Some("backspace"), _) => {
+                self.prompt.backspace();
+                self.prompt.redraw(output);
+            }
+            (Some("complete"), _) if matches!(self.after_prompt, AfterPrompt::SwitchBuffer) => {
+                let input = self.buffers.complete(self.prompt.result(), &self.buf);
+                self.prompt.set_result(&input);
+                self.prompt.redraw(output);
+            }
+            (Some("complete"), _) => {
+                self.prompt.push('\t');
+                self.prompt.redraw(output);
+            }
+            (Some("accept"), _) => {
+                self.edit_mode = EditMode::Editor;
+                self.input = String::from(self.prompt.result());
+                self.prompt.clear(output);
+                self.status.redraw(output);
+                self.focus_edit_window(output);
+                self.after_prompt(output);
+            }
+            (None, Key::Char(c)) => {
+                self.prompt.push(c);
+                self.prompt.redraw(output);
+            }
+            _ => {}
+        }
+        output.flush().unwrap();
+    }
+    /// act on the input of the prompt.
+    fn after_prompt<W: Write>(&mut self, output: &mut W) {
+        match std::mem::replace(&mut self.after_prompt, AfterPrompt::None) {
+            AfterPrompt::SaveFileAs => {
+                if let Err(e) = self.buf.save_file_as(&self.input) {
+                    self.prompt.set_prompt(e);
+                    self.prompt.redraw(output);
                 }
-                "save_file_as" => {
-                    self.buf.save_file_as(&cmd.argstr);
+                self.status.set_file_name(self.buf.file_name());
+                self.status.redraw(output);
+            }
+            AfterPrompt::QueryReplaceFrom => {
+                self.query_replace_from(output);
+            }
+            AfterPrompt::PlayMacro => match self.input.trim().parse() {
+                Ok(times) => self.play_macro(times, output),
+                Err(_) => self.message("Not a number", output),
+            },
+            AfterPrompt::GotoLine => {
+                let cmd = MacroCommand::new("goto_line", self.input.trim());
+                self.execute_key_command(cmd, None, false, output);
+                self.focus_edit_window(output);
+            }
+            AfterPrompt::FindFile => {
+                let cmd = MacroCommand::new("find_file", self.input.trim());
+                self.execute_key_command(cmd, None, false, output);
+                self.focus_edit_window(output);
+            }
+            AfterPrompt::SwitchBuffer => {
+                let cmd = MacroCommand::new("switch_buffer", &self.input);
+                self.execute_key_command(cmd, None, false, output);
+                self.focus_edit_window(output);
+            }
+            AfterPrompt::SaveMacro => {
+                let file_name = self.input.clone();
+                self.save_macro(&file_name, output);
+            }
+            AfterPrompt::QueryReplaceTo => {
+                self.query_replace_to(output);
+            }
+            AfterPrompt::ScriptCommand => {
+                let result = self.script.eval(&self.input, &mut self.buf);
+                if let Err(e) = self.script_done(result, output) {
+                    self.message(&e, output);
                 }
-                "save_file" => {
-                    self.buf.save_file().unwrap();
+            }
+            AfterPrompt::ScriptPrompt(callback) => {
+                let result = self
+                    .script
+                    .call(&callback, Some(&self.input), &mut self.buf);
+                if let Err(e) = self.script_done(result, output) {
+                    self.message(&e, output);
+                }
+            }
+            _ => {}
+        }
+    }
+    /// run the command of the one key input. An unbound printable key is the answer.
+    /// Returns false when the editor should exit.
+    fn one_key_input<W: Write>(
+        &mut self,
+        command: Option<String>,
+        key: Key,
+        output: &mut W,
+    ) -> bool {
+        if let AfterPrompt::BufferList = self.after_prompt {
+            // any key closes the buffer list, and a digit switches to the buffer.
+            self.close_buffer_list(output);
+            let index = match key {
+                Key::Char(c) => c.to_digit(10).unwrap_or(0) as usize,
+                _ => 0,
+            };
+            if index > 0 && index <= self.buffers.len() {
+                let name = self.buffers.list(&self.buf, false)[index - 1].0.clone();
+                let cmd = MacroCommand::new("switch_buffer", &name);
+                self.execute_key_command(cmd, None, false, output);
+            }
+            self.focus_edit_window(output);
+            return true;
+        }
+        match (command.as_deref(), key) {
+            (Some("cancel"), _) if matches!(self.after_prompt, AfterPrompt::QueryReplace) => {
+                self.query_replace_finish(output);
+            }
+            (Some("cancel"), _) => {
+                self.edit_mode = EditMode::Editor;
+                self.after_prompt = AfterPrompt::None;
+                self.prompt.set_prompt("");
+                self.prompt.clear(output);
+                self.focus_edit_window(output);
+            }
+            (None, Key::Char(c)) => match &mut self.after_prompt {
+                AfterPrompt::ExitY => {
+                    if c == 'y' || c == '\n' {
+                        return false;
+                    } else {
+                        self.edit_mode = EditMode::Editor;
+                        self.prompt.set_prompt("");
+                        self.prompt.clear(output);
+                        self.focus_edit_window(output);
+                    }
                 }
-                "cursor_up" => {
-                    self.buf.cursor_up(&mut stdout);
+                AfterPrompt::QueryReplace => {
+                    self.query_replace_key(c, output);
                 }
-                "cursor_down" => {
-                    self.buf.cursor_down(&mut stdout);
+                AfterPrompt::CloseBufferY => {
+                    self.edit_mode = EditMode::Editor;
+                    self.after_prompt = AfterPrompt::None;
+                    self.prompt.set_prompt("");
+                    self.prompt.clear(output);
+                    if c == 'y' {
+                        let cmd = MacroCommand::new("close_buffer", "");
+                        self.execute_key_command(cmd, None, false, output);
+                    }
+                    self.focus_edit_window(output);
                 }
-                "cursor_left" => {
-                    self.buf.cursor_left(&mut stdout);
+                _ => {}
+            },
+            _ => {}
+        }
+        true
+    }
+    fn close_buffer_list<W: Write>(&mut self, output: &mut W) {
+        self.edit_mode = EditMode::Editor;
+        self.after_prompt = AfterPrompt::None;
+        self.prompt.set_prompt("");
+        self.prompt.clear(output);
+        self.buf.redraw(output);
+    }
+    pub fn run_script(&mut self, script: &[MacroCommand]) -> Result<(), String> {
+        // let mut stdout = stdout().into_raw_mode().unwrap();
+        let mut stdout = stdout()
+            .into_raw_mode()
+            .unwrap()
+            .into_alternate_screen()
+            .unwrap();
+        self.run_script_on(script, &mut stdout)
+    }
+    /// run the script without a terminal, e.g. in CI or a pipeline.
+    pub fn run_script_headless(&mut self, script: &[MacroCommand]) -> Result<(), String> {
+        self.run_script_on(script, &mut io::sink())
+    }
+    /// run the script drawing to `output`, which may be an in-memory screen.
+    /// The drawing is also interpreted on a virtual screen, which `assert_*` commands check.
+    /// Stops at the first failed command, and returns the error with the 1-based command index.
+    pub fn run_script_on<W: Write>(
+        &mut self,
+        script: &[MacroCommand],
+        output: &mut W,
+    ) -> Result<(), String> {
+        let screen = *self.buf.window().screen();
+        let output = &mut Tee {
+            output,
+            screen: VirtualScreen::new(screen.width, screen.height),
+        };
+        self.buf.redraw(output);
+        self.status.redraw(output);
+        self.run_commands(script, "", output).map(|_| ())
+    }
+    /// run the commands of a script or the body of `repeat`, `while` and `if`.
+    /// `index` is the index of the enclosing command such as "3.", for error messages.
+    fn run_commands<W: Write>(
+        &mut self,
+        commands: &[MacroCommand],
+        index: &str,
+        output: &mut Tee<W>,
+    ) -> Result<Flow, String> {
+        for (i, cmd) in commands.iter().enumerate() {
+            let index = format!("{}{}", index, i + 1);
+            let fail = |e: &str| format!("command {}:{}:{}", index, cmd.name, e);
+            let body = format!("{}.", index);
+            let last_yank = self.last_yank.take();
+            let last_kill = std::mem::replace(&mut self.last_kill, false);
+            match cmd.name.as_str() {
+                "repeat" => {
+                    // `arg` times, or until `break` if `until_break`, failing after
+                    // `MAX_REPEAT` times so that a script without a reachable `break` ends.
+                    let times = match (cmd.until_break, cmd.arg) {
+                        (true, _) => MAX_REPEAT,
+                        (false, 0) => return Err(fail("arg or until_break is required")),
+                        (false, n) => n,
+                    };
+                    let mut broken = false;
+                    for _ in 0..times {
+                        if let Flow::Break = self.run_commands(&cmd.commands, &body, output)? {
+                            broken = true;
+                            break;
+                        }
+                    }
+                    if cmd.until_break && !broken {
+                        return Err(fail(&format!("no break in {} times", MAX_REPEAT)));
+                    }
                 }
-                "cursor_right" => {
-                    self.buf.cursor_right(&mut stdout);
+                "while" => {
+                    // fails after `MAX_REPEAT` times, if the condition stays true.
+                    let mut n = 0;
+                    while self.condition(cmd).map_err(|e| fail(&e))? {
+                        if n == MAX_REPEAT {
+                            return Err(fail(&format!("no end in {} times", MAX_REPEAT)));
+                        }
+                        n += 1;
+                        if let Flow::Break = self.run_commands(&cmd.commands, &body, output)? {
+                            break;
+                        }
+                    }
                 }
-                "insert_char" => {
-                    self.buf.insert_char(cmd.argstr.chars().nth(0).unwrap());
-                    self.buf.redraw(&mut stdout);
+                "if" => {
+                    let commands = if self.condition(cmd).map_err(|e| fail(&e))? {
+                        &cmd.commands
+                    } else {
+                        &cmd.else_commands
+                    };
+                    if let Flow::Break = self.run_commands(commands, &body, output)? {
+                        return Ok(Flow::Break);
+                    }
                 }
                 "break" => {
-                    break;
+                    return Ok(Flow::Break);
+                }
+                "resize" => match parse_size(&cmd.argstr) {
+                    Some((width, height)) => {
+                        output.screen.resize(width, height);
+                        self.resize(Screen { width, height }, output);
+                    }
+                    None => return Err(fail(&format!("invalid size:{}", cmd.argstr))),
+                },
+                name if name.starts_with("assert_") => {
+                    if let Err(e) = self.check(cmd, &output.screen) {
+                        return Err(fail(&e));
+                    }
+                }
+                _ => {
+                    if let Err(e) = self.execute(cmd, last_yank, last_kill, output) {
+                        return Err(fail(&e));
+                    }
                 }
-                _ => {}
             }
-            self.status.redraw(&mut stdout);
+            self.status.redraw(output);
             write!(
-                stdout,
+                output,
                 "{}",
                 cursor::Goto(self.buf.window().scr_cur_x(), self.buf.window().scr_cur_y())
             )
             .unwrap();
-            stdout.flush().unwrap();
+            output.flush().unwrap();
+        }
+        Ok(Flow::Next)
+    }
+    /// condition of `while` and `if` in `argstr`, negated by a leading "!".
+    /// - `eof`: the cursor is at the end of the text.
+    /// - `last_line`: the cursor is on the last line.
+    /// - `match`: the current line matches the regexp `argstr2`.
+    fn condition(&mut self, cmd: &MacroCommand) -> Result<bool, String> {
+        let (negate, name) = match cmd.argstr.strip_prefix('!') {
+            Some(name) => (true, name),
+            None => (false, cmd.argstr.as_str()),
+        };
+        let text = self.buf.text();
+        let result = match name {
+            "eof" => self.buf.cursor_pos() >= text.len_chars(),
+            "last_line" => self.buf.cur_y() + 1 >= text.len_lines(),
+            "match" => Regex::new(&cmd.argstr2)
+                .map_err(|e| format!("invalid regexp:{}", e))?
+                .is_match(&text.line(self.buf.cur_y())),
+            _ => return Err(format!("unknown condition:{}", cmd.argstr)),
+        };
+        Ok(result != negate)
+    }
+    /// check the state of the buffer or the virtual screen by the assert command.
+    /// - `assert_screen`: the row `arg` is `argstr`, without trailing spaces.
+    /// - `assert_cursor`: the cursor is at `argstr`, "x,y" on the screen or "line:column" in the buffer.
+    /// - `assert_status`: the words on the status bar are `argstr`, e.g. "a.txt * Ins".
+    /// - `assert_line`: the line `arg` (the current line if 0) is `argstr`.
+    /// - `assert_modified`: the buffer is modified if `arg` is 1, not modified if 0.
+    /// - `assert_register`: the register `argstr` has `argstr2`.
+    fn check(&mut self, cmd: &MacroCommand, screen: &VirtualScreen) -> Result<(), String> {
+        let (expected, actual) = match cmd.name.as_str() {
+            "assert_cursor" if cmd.argstr.contains(':') => (
+                cmd.argstr.replace(' ', ""),
+                format!("{}:{}", self.buf.cur_y() + 1, self.buf.cur_x() + 1),
+            ),
+            "assert_line" => {
+                let y = match cmd.arg {
+                    0 => self.buf.cur_y(),
+                    line => line - 1,
+                };
+                if y >= self.buf.text().len_lines() {
+                    return Err(format!("no line {} in the buffer", y + 1));
+                }
+                (cmd.argstr.clone(), self.buf.text().line(y))
+            }
+            "assert_modified" => ((cmd.arg != 0).to_string(), self.changed.to_string()),
+            "assert_register" => (
+                cmd.argstr2.clone(),
+                self.registers.get(&cmd.argstr).cloned().unwrap_or_default(),
+            ),
+            "assert_screen" => {
+                if cmd.arg == 0 || cmd.arg > screen.height() as usize {
+                    return Err(format!("no row {} on the screen", cmd.arg));
+                }
+                (cmd.argstr.clone(), screen.row(cmd.arg as u16))
+            }
+            "assert_cursor" => {
+                let (x, y) = screen.cursor();
+                (cmd.argstr.replace(' ', ""), format!("{},{}", x, y))
+            }
+            "assert_status" => {
+                let row = screen.row(self.status.window().y());
+                let words: Vec<&str> = row.split_whitespace().collect();
+                (cmd.argstr.clone(), words.join(" "))
+            }
+            _ => return Err(String::from("unknown command")),
+        };
+        if expected == actual {
+            Ok(())
+        } else {
+            Err(format!("expected {:?}, but {:?}", expected, actual))
         }
     }
 }
+
+/// the area of the windows: the edit window and the status line below it, within the screen.
+fn edit_area(editor: &Window, screen: Screen) -> Rect {
+    let rows = screen.height.saturating_sub(editor.y()) + 1;
+    Rect {
+        x: editor.x(),
+        y: editor.y(),
+        width: editor.width(),
+        height: (editor.height() + 1).min(rows),
+    }
+}
+
+/// "WIDTHxHEIGHT" such as "80x24".
+fn parse_size(s: &str) -> Option<(u16, u16)> {
+    let (width, height) = s.split_once('x')?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// Writes to the output and the virtual screen at once.
+struct Tee<'a, W: Write> {
+    output: &'a mut W,
+    screen: VirtualScreen,
+}
+
+impl<'a, W: Write> Write for Tee<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write_all(buf)?;
+        self.screen.write_all(buf)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor() -> Editor {
+        let layout = Screen {
+            width: 40,
+            height: 10,
+        }
+        .layout();
+        let mut editor = Editor::new(
+            layout.editor,
+            StatusBar::new(layout.status),
+            Prompt::new(layout.prompt),
+        );
+        editor.buf.new_buffer();
+        editor
+    }
+
+    #[test]
+    fn input_keys_without_terminal() {
+        let mut editor = editor();
+        let mut output: Vec<u8> = vec![];
+        for key in [Key::Char('a'), Key::Char('\n'), Key::Char('b')] {
+            assert!(editor.input_key(key, &mut output));
+        }
+        assert_eq!(editor.buf.cursor_pos(), 3);
+        assert!(String::from_utf8(output).unwrap().contains('b'));
+    }
+    #[test]
+    fn key_sequences_and_describe_key() {
+        let mut editor = editor();
+        let mut screen = VirtualScreen::new(40, 10);
+        editor.input_key(Key::Char('a'), &mut screen);
+        editor.input_key(Key::Ctrl('x'), &mut screen);
+        assert_eq!(screen.row(10), "C-x-");
+        assert!(editor.input_key(Key::Ctrl('q'), &mut screen));
+        assert_eq!(screen.row(10), "C-x C-q is undefined");
+        assert_eq!(editor.buf.text().to_string(), "a");
+        for key in [
+            Key::Ctrl('h'),
+            Key::Char('k'),
+            Key::Ctrl('x'),
+            Key::Ctrl('s'),
+        ] {
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(screen.row(10), "C-x C-s runs save_file");
+        for key in [Key::Ctrl('h'), Key::Char('k'), Key::Char('b')] {
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(screen.row(10), "b runs insert_char");
+        assert_eq!(editor.buf.text().to_string(), "a");
+        // C-x C-c asks before exiting a modified buffer, Esc cancels.
+        editor.input_key(Key::Ctrl('x'), &mut screen);
+        editor.input_key(Key::Ctrl('c'), &mut screen);
+        assert!(editor.input_key(Key::Esc, &mut screen));
+        assert!(matches!(editor.edit_mode, EditMode::Editor));
+    }
+    #[test]
+    fn keymap_from_config() {
+        let mut editor = editor();
+        let config = Config::from_json(
+            r#"{"keymap": {"editor": {"C-t": "undo", "C-z": ""}, "prompt": {"Esc": "cancel"}}}"#,
+        )
+        .unwrap();
+        editor.set_config(&config);
+        let mut output: Vec<u8> = vec![];
+        for key in [Key::Char('a'), Key::Ctrl('z')] {
+            editor.input_key(key, &mut output);
+        }
+        assert_eq!(editor.buf.text().to_string(), "a");
+        editor.input_key(Key::Ctrl('t'), &mut output);
+        assert_eq!(editor.buf.text().to_string(), "");
+        editor.input_key(Key::Ctrl('a'), &mut output);
+        assert!(matches!(editor.edit_mode, EditMode::Prompt));
+        editor.input_key(Key::Esc, &mut output);
+        assert!(matches!(editor.edit_mode, EditMode::Editor));
+    }
+    #[test]
+    fn buffer_list_and_close_buffer() {
+        let mut editor = editor();
+        let mut screen = VirtualScreen::new(40, 10);
+        editor.add_buffer("tests/script/test1/macro.json").unwrap();
+        editor.add_buffer("no_such_file.txt").unwrap();
+        editor.input_key(Key::Char('a'), &mut screen);
+        for key in [Key::Ctrl('x'), Key::Ctrl('b')] {
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(screen.row(1), ">1 * [NEW FILE]");
+        assert_eq!(screen.row(3), " 3   no_such_file.txt");
+        editor.input_key(Key::Char('3'), &mut screen);
+        assert!(screen.row(9).starts_with("[3/3] no_such_file.txt "));
+        assert_eq!(editor.buf.file_name(), "no_such_file.txt");
+        // switch by name with completion.
+        for key in [
+            Key::Ctrl('x'),
+            Key::Char('b'),
+            Key::Char('t'),
+            Key::Char('\t'),
+        ] {
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(editor.prompt.result(), "tests/script/test1/macro.json");
+        editor.input_key(Key::Char('\n'), &mut screen);
+        assert_eq!(editor.buffers.current(), 1);
+        // closing a modified buffer asks.
+        for key in [Key::Ctrl('x'), Key::Left, Key::Ctrl('x'), Key::Char('k')] {
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(screen.row(10), "Buffer is modified. Close? [y/N]");
+        editor.input_key(Key::Char('n'), &mut screen);
+        assert_eq!(editor.buffers.len(), 3);
+        for key in [Key::Ctrl('x'), Key::Char('k'), Key::Char('y')] {
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(editor.buffers.len(), 2);
+        assert_eq!(editor.buf.file_name(), "tests/script/test1/macro.json");
+        assert!(!editor.changed);
+    }
+    #[test]
+    fn split_windows_share_the_buffer() {
+        let mut editor = editor();
+        let mut screen = VirtualScreen::new(40, 10);
+        for key in [Key::Char('a'), Key::Ctrl('x'), Key::Char('2')] {
+            editor.input_key(key, &mut screen);
+        }
+        for key in [Key::Char('b'), Key::Ctrl('x'), Key::Char('o')] {
+            editor.input_key(key, &mut screen);
+        }
+        // the cursor of the lower window stays after "a".
+        editor.input_key(Key::Char('c'), &mut screen);
+        assert_eq!(screen.row(1), "acb");
+        assert_eq!(screen.row(5), "acb");
+        assert_eq!(screen.cursor(), (3, 5));
+        // side by side in the lower window.
+        for key in [
+            Key::Ctrl('x'),
+            Key::Char('3'),
+            Key::Ctrl('x'),
+            Key::Char('}'),
+        ] {
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(screen.row(5), format!("acb{}|acb", " ".repeat(17)));
+        for key in [Key::Ctrl('x'), Key::Char('1')] {
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(screen.row(5), "");
+        assert!(screen.row(9).ends_with("* Ins"));
+    }
+    #[test]
+    fn edit_above_the_view_of_other_window() {
+        let mut editor = editor();
+        let mut screen = VirtualScreen::new(40, 10);
+        editor.buf.insert_str("1\n2\n3\n4\n5\n6");
+        editor.buf.goto(0, 4);
+        for key in [Key::Ctrl('x'), Key::Char('2')] {
+            editor.input_key(key, &mut screen);
+        }
+        // the upper window edits above the cursor of the lower one.
+        editor.buf.goto(0, 0);
+        for key in [Key::Char('\n'), Key::Char('\n')] {
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(editor.windows.get(1).view.cur_y, 6);
+        assert_eq!(screen.cursor(), (1, 3));
+        editor.input_key(Key::Backspace, &mut screen);
+        assert_eq!(editor.windows.get(1).view.cur_y, 5);
+        for key in [Key::Ctrl('x'), Key::Char('o')] {
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!((editor.buf.cur_x(), editor.buf.cur_y()), (0, 5));
+        assert_eq!(editor.buf.text().line(5).to_string(), "5");
+    }
+    #[test]
+    fn vi_mode_keys() {
+        let mut editor = editor();
+        editor.set_config(&Config::from_json(r#"{"vi_mode": true}"#).unwrap());
+        let mut screen = VirtualScreen::new(40, 10);
+        for c in "ifoo bar\u{1b}0dwx.".chars() {
+            let key = if c == '\u{1b}' {
+                Key::Esc
+            } else {
+                Key::Char(c)
+            };
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(editor.buf.text().to_string(), "r");
+        assert!(screen.row(9).ends_with("* NORMAL"), "{}", screen.row(9));
+        for c in "A baz\u{1b}0.".chars() {
+            let key = if c == '\u{1b}' {
+                Key::Esc
+            } else {
+                Key::Char(c)
+            };
+            editor.input_key(key, &mut screen);
+        }
+        assert_eq!(editor.buf.text().to_string(), "r baz baz");
+        editor.input_key(Key::Char('o'), &mut screen);
+        assert!(screen.row(9).ends_with("* INSERT"), "{}", screen.row(9));
+        // keys which are not vi commands run the editor commands.
+        editor.input_key(Key::Ctrl('c'), &mut screen);
+        assert!(matches!(editor.edit_mode, EditMode::OneKeyInput));
+    }
+    #[test]
+    fn exit_modified_buffer_after_confirmation() {
+        let mut editor = editor();
+        let mut output: Vec<u8> = vec![];
+        assert!(editor.input_key(Key::Char('a'), &mut output));
+        assert!(editor.input_key(Key::Ctrl('c'), &mut output));
+        assert!(!editor.input_key(Key::Char('y'), &mut output));
+    }
+    #[test]
+    fn record_and_play_keyboard_macro() {
+        let mut editor = editor();
+        let mut output: Vec<u8> = vec![];
+        for key in [
+            Key::F(3),
+            Key::Char('a'),
+            Key::Char('b'),
+            Key::Left,
+            Key::F(4),
+        ] {
+            assert!(editor.input_key(key, &mut output));
+        }
+        assert_eq!(editor.keyboard_macro.len(), 3);
+        assert_eq!(
+            editor.keyboard_macro[0],
+            MacroCommand::new("insert_char", "a")
+        );
+        assert!(editor.input_key(Key::F(4), &mut output));
+        assert_eq!(editor.buf.text().to_string(), "aabb");
+    }
+    #[test]
+    fn keyboard_macro_records_search_and_prompt_keys() {
+        let mut editor = editor();
+        let mut output: Vec<u8> = vec![];
+        editor.buf.insert_str("ab\nab\nab");
+        editor.buf.goto(0, 0);
+        let keys = "F3 C-f b Enter x M-g + 1 Enter F4";
+        for key in parse_keys(keys).unwrap() {
+            assert!(editor.input_key(key, &mut output));
+        }
+        assert_eq!(
+            editor.keyboard_macro,
+            [
+                MacroCommand::new("input_keys", "C-f b Enter"),
+                MacroCommand::new("insert_char", "x"),
+                MacroCommand::new("goto_line", "+1"),
+            ]
+        );
+        assert!(editor.input_key(Key::F(4), &mut output));
+        assert_eq!(editor.buf.text().to_string(), "axb\naxb\nab");
+    }
+    #[test]
+    fn saved_keyboard_macro_can_be_run_as_script() {
+        let mut editor = editor();
+        let mut output: Vec<u8> = vec![];
+        for key in [
+            Key::F(3),
+            Key::Char('x'),
+            Key::Char('\n'),
+            Key::Char('y'),
+            Key::F(4),
+        ] {
+            assert!(editor.input_key(key, &mut output));
+        }
+        let file_name = std::env::temp_dir().join("ked_test_keyboard_macro.json");
+        let file_name = file_name.to_str().unwrap();
+        editor.save_macro(file_name, &mut output);
+
+        let file = File::open(file_name).unwrap();
+        let script: Vec<MacroCommand> = serde_json::from_reader(file).unwrap();
+        std::fs::remove_file(file_name).ok();
+        let mut replay = self::editor();
+        replay.run_script_on(&script, &mut io::sink()).unwrap();
+        assert_eq!(replay.buf.text().to_string(), "x\ny");
+    }
+    #[test]
+    fn repeat_needs_a_count_or_until_break() {
+        let script: Vec<MacroCommand> =
+            serde_json::from_str(r#"[{"name": "repeat", "commands": [{"name": "cursor_right"}]}]"#)
+                .unwrap();
+        assert_eq!(
+            editor().run_script_headless(&script),
+            Err("command 1:repeat:arg or until_break is required".to_string())
+        );
+        let script: Vec<MacroCommand> = serde_json::from_str(
+            r#"[{"name": "repeat", "until_break": true, "commands": [{"name": "cursor_right"}]}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            editor().run_script_headless(&script),
+            Err(format!("command 1:repeat:no break in {} times", MAX_REPEAT))
+        );
+    }
+    #[test]
+    fn while_stops_at_the_limit() {
+        let script: Vec<MacroCommand> = serde_json::from_str(
+            r#"[{"name": "while", "argstr": "!eof", "commands": [{"name": "cursor_left"}]}]"#,
+        )
+        .unwrap();
+        let mut editor = editor();
+        editor.buf.insert_str("ab");
+        editor.buf.goto(0, 0);
+        assert_eq!(
+            editor.run_script_headless(&script),
+            Err(format!("command 1:while:no end in {} times", MAX_REPEAT))
+        );
+    }
+    #[test]
+    fn every_command_is_executable() {
+        let file_name = std::env::temp_dir().join("ked_test_every_command.txt");
+        for command in COMMANDS {
+            let mut editor = editor();
+            let cmd = MacroCommand::new(command.name, file_name.to_str().unwrap());
+            let result = editor.execute(&cmd, None, false, &mut io::sink());
+            assert_ne!(
+                result,
+                Err(String::from("unknown command")),
+                "{}",
+                command.name
+            );
+        }
+        std::fs::remove_file(&file_name).ok();
+        let cmd = MacroCommand::new("no_such_command", "");
+        let result = editor().execute(&cmd, None, false, &mut io::sink());
+        assert_eq!(result, Err(String::from("unknown command")));
+    }
+    #[test]
+    fn arg_repeats_command() {
+        let mut editor = editor();
+        let mut cmd = MacroCommand::new("insert_string", "ab");
+        cmd.arg = 3;
+        editor.execute(&cmd, None, false, &mut io::sink()).unwrap();
+        let mut cmd = MacroCommand::new("backspace", "");
+        cmd.arg = 2;
+        editor.execute(&cmd, None, false, &mut io::sink()).unwrap();
+        assert_eq!(editor.buf.text().to_string(), "abab");
+    }
+}
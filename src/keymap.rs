@@ -0,0 +1,333 @@
+use std::collections::{BTreeMap, HashMap};
+
+use termion::event::Key;
+
+use crate::config::KeymapConfig;
+use crate::macrocommand::{find_command, COMMANDS};
+
+/// Commands of the editor mode besides `COMMANDS`, with their default keys.
+/// Key bindings to a script function are named `script:FUNCTION`.
+const EDITOR_BINDINGS: &[(&str, &str)] = &[
+    ("exit", "C-c"),
+    ("exit", "C-x C-c"),
+    ("save_file", "C-s"),
+    ("save_file", "C-x C-s"),
+    ("save_file_as", "C-a"), // asks the file name
+    ("save_file_as", "C-x C-w"),
+    ("search_forward", "C-f"),
+    ("search_backward", "C-r"),
+    ("search_regex_forward", "M-s"),
+    ("search_regex_backward", "M-r"),
+    ("query_replace", "M-%"),
+    ("goto_line", "M-g"), // asks the line
+    ("find_file", "C-x C-f"),
+    ("switch_buffer", "C-x b"), // asks the name with completion
+    ("next_buffer", "C-x Right"),
+    ("previous_buffer", "C-x Left"),
+    ("close_buffer", "C-x k"), // asks if modified
+    ("list_buffers", "C-x C-b"),
+    ("split_window", "C-x 2"),
+    ("split_window_vertically", "C-x 3"),
+    ("other_window", "C-x o"),
+    ("delete_window", "C-x 0"),
+    ("delete_other_windows", "C-x 1"),
+    ("enlarge_window", "C-x ^"),
+    ("shrink_window", "C-x -"),
+    ("enlarge_window_horizontally", "C-x }"),
+    ("shrink_window_horizontally", "C-x {"),
+    ("script_command", "M-x"),
+    ("start_macro", "F3"),
+    ("end_or_play_macro", "F4"),
+    ("play_macro", "F5"), // asks how many times
+    ("save_macro", "F6"),
+    ("describe_key", "C-h k"),
+];
+
+/// Commands of the prompt mode. Other keys are input.
+const PROMPT_BINDINGS: &[(&str, &str)] = &[
+    ("cancel", "C-c"),
+    ("backspace", "Backspace"),
+    ("accept", "Enter"),
+    ("complete", "Tab"), // buffer names. Inserts a tab in other prompts
+];
+
+/// Commands of the incremental search. Other keys are added to the pattern.
+const SEARCH_BINDINGS: &[(&str, &str)] = &[
+    ("cancel", "C-c"),
+    ("cancel", "Esc"),
+    ("search_forward", "C-f"),
+    ("search_backward", "C-r"),
+    ("backspace", "Backspace"),
+    ("accept", "Enter"),
+];
+
+/// Commands of the one key input, such as y/n questions. Other keys are the answer.
+const ONE_KEY_BINDINGS: &[(&str, &str)] = &[("cancel", "C-c"), ("cancel", "Esc")];
+
+/// Result of looking up a key sequence.
+#[derive(Debug, PartialEq)]
+pub enum Lookup {
+    Command(String),
+    Prefix, // more keys are needed
+    Undefined,
+}
+
+/// Key sequences bound to command names.
+#[derive(Clone, Default)]
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, String>,
+}
+
+impl Keymap {
+    fn from_table(table: &[(&str, &str)]) -> Self {
+        let mut keymap = Keymap::default();
+        for (command, keys) in table {
+            keymap.bind(parse_keys(keys).unwrap(), command);
+        }
+        keymap
+    }
+    /// bind the key sequence. Bindings which are a prefix of it, or which it is a prefix of,
+    /// are removed. An empty command only unbinds.
+    pub fn bind(&mut self, keys: Vec<Key>, command: &str) {
+        self.bindings
+            .retain(|k, _| !(k.starts_with(&keys) || keys.starts_with(k)));
+        if !command.is_empty() {
+            self.bindings.insert(keys, command.to_string());
+        }
+    }
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        if let Some(command) = self.bindings.get(keys) {
+            Lookup::Command(command.clone())
+        } else if self
+            .bindings
+            .keys()
+            .any(|k| k.len() > keys.len() && k.starts_with(keys))
+        {
+            Lookup::Prefix
+        } else {
+            Lookup::Undefined
+        }
+    }
+    /// override the bindings by `config`, checking the keys and the commands.
+    fn apply(
+        &mut self,
+        mode: &str,
+        config: &BTreeMap<String, String>,
+        table: &[(&str, &str)],
+    ) -> Result<(), String> {
+        for (keys, command) in config {
+            let error = |e: &str| format!("keymap.{}:{}:{}", mode, keys, e);
+            let valid = command.is_empty()
+                || table.iter().any(|(name, _)| name == command)
+                || (mode == "editor"
+                    && (find_command(command).is_some() || command.starts_with("script:")));
+            if !valid {
+                return Err(error(&format!("unknown command {}", command)));
+            }
+            let keys = parse_keys(keys).ok_or_else(|| error("invalid key"))?;
+            self.bind(keys, command);
+        }
+        Ok(())
+    }
+}
+
+/// Keymap for each mode of the editor.
+#[derive(Clone)]
+pub struct Keymaps {
+    pub editor: Keymap,
+    pub prompt: Keymap,
+    pub search: Keymap,
+    pub one_key: Keymap,
+}
+
+impl Default for Keymaps {
+    fn default() -> Self {
+        let mut editor = Keymap::from_table(EDITOR_BINDINGS);
+        for command in COMMANDS {
+            for key in command.keys {
+                editor.bind(vec![*key], command.name);
+            }
+        }
+        Keymaps {
+            editor,
+            prompt: Keymap::from_table(PROMPT_BINDINGS),
+            search: Keymap::from_table(SEARCH_BINDINGS),
+            one_key: Keymap::from_table(ONE_KEY_BINDINGS),
+        }
+    }
+}
+
+impl Keymaps {
+    /// default keymaps overridden by the config.
+    pub fn new(config: &KeymapConfig) -> Result<Self, String> {
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .editor
+            .apply("editor", &config.editor, EDITOR_BINDINGS)?;
+        keymaps
+            .prompt
+            .apply("prompt", &config.prompt, PROMPT_BINDINGS)?;
+        keymaps
+            .search
+            .apply("search", &config.search, SEARCH_BINDINGS)?;
+        keymaps
+            .one_key
+            .apply("one_key", &config.one_key, ONE_KEY_BINDINGS)?;
+        Ok(keymaps)
+    }
+}
+
+/// key by name, such as "C-t" (Ctrl), "M-x" (Alt), "F5", "Up" or "a".
+pub fn parse_key(s: &str) -> Option<Key> {
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    if let Some(c) = s.strip_prefix("C-").and_then(single) {
+        return Some(Key::Ctrl(c));
+    }
+    if let Some(c) = s.strip_prefix("M-").and_then(single) {
+        return Some(Key::Alt(c));
+    }
+    if let Some(n) = s.strip_prefix('F').and_then(|n| n.parse().ok()) {
+        return Some(Key::F(n));
+    }
+    match s {
+        "C-Left" => Some(Key::CtrlLeft),
+        "C-Right" => Some(Key::CtrlRight),
+        "Up" => Some(Key::Up),
+        "Down" => Some(Key::Down),
+        "Left" => Some(Key::Left),
+        "Right" => Some(Key::Right),
+        "Home" => Some(Key::Home),
+        "End" => Some(Key::End),
+        "PageUp" => Some(Key::PageUp),
+        "PageDown" => Some(Key::PageDown),
+        "Insert" => Some(Key::Insert),
+        "Delete" => Some(Key::Delete),
+        "Backspace" => Some(Key::Backspace),
+        "Tab" => Some(Key::Char('\t')),
+        "Enter" => Some(Key::Char('\n')),
+        "Esc" => Some(Key::Esc),
+        "SPC" => Some(Key::Char(' ')),
+        "C-SPC" => Some(Key::Null),
+        _ => single(s).map(Key::Char),
+    }
+}
+
+/// key sequence separated by spaces, such as "C-x C-s".
+pub fn parse_keys(s: &str) -> Option<Vec<Key>> {
+    let keys: Option<Vec<Key>> = s.split_whitespace().map(parse_key).collect();
+    keys.filter(|keys| !keys.is_empty())
+}
+
+/// name of the key, which `parse_key` accepts.
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Ctrl(c) => format!("C-{}", c),
+        Key::Alt(c) => format!("M-{}", c),
+        Key::F(n) => format!("F{}", n),
+        Key::Char('\t') => String::from("Tab"),
+        Key::Char('\n') => String::from("Enter"),
+        Key::Char(' ') => String::from("SPC"),
+        Key::Char(c) => c.to_string(),
+        Key::Null => String::from("C-SPC"),
+        Key::Esc => String::from("Esc"),
+        Key::CtrlLeft => String::from("C-Left"),
+        Key::CtrlRight => String::from("C-Right"),
+        key => format!("{:?}", key),
+    }
+}
+
+/// names of the keys separated by spaces.
+pub fn keys_name(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| key_name(*key))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_keymap() {
+        let keymaps = Keymaps::default();
+        let command = |s: &str| Lookup::Command(String::from(s));
+        assert_eq!(keymaps.editor.lookup(&[Key::Up]), command("cursor_up"));
+        assert_eq!(
+            keymaps.editor.lookup(&[Key::Char('\n')]),
+            command("newline")
+        );
+        assert_eq!(keymaps.editor.lookup(&[Key::Ctrl('x')]), Lookup::Prefix);
+        assert_eq!(
+            keymaps.editor.lookup(&[Key::Ctrl('x'), Key::Ctrl('s')]),
+            command("save_file")
+        );
+        assert_eq!(keymaps.editor.lookup(&[Key::Char('a')]), Lookup::Undefined);
+        assert_eq!(keymaps.editor.lookup(&[Key::F(12)]), Lookup::Undefined);
+        assert_eq!(keymaps.prompt.lookup(&[Key::Char('\n')]), command("accept"));
+        assert_eq!(keymaps.one_key.lookup(&[Key::Esc]), command("cancel"));
+    }
+    #[test]
+    fn keys_are_bound_once() {
+        let mut keys: Vec<Vec<Key>> = EDITOR_BINDINGS
+            .iter()
+            .map(|(_, keys)| parse_keys(keys).unwrap())
+            .collect();
+        keys.extend(
+            COMMANDS
+                .iter()
+                .flat_map(|c| c.keys.iter().map(|k| vec![*k])),
+        );
+        for (i, key) in keys.iter().enumerate() {
+            assert!(!keys[i + 1..].contains(key), "{:?}", key);
+        }
+    }
+    #[test]
+    fn rebind_from_config() {
+        let mut config = KeymapConfig::default();
+        config.editor.insert("C-x".into(), "kill_region".into());
+        config.editor.insert("C-f".into(), "".into());
+        config
+            .editor
+            .insert("C-t C-t".into(), "script:hello".into());
+        config.prompt.insert("Esc".into(), "cancel".into());
+        let keymaps = Keymaps::new(&config).unwrap();
+        assert_eq!(
+            keymaps.editor.lookup(&[Key::Ctrl('x')]),
+            Lookup::Command(String::from("kill_region"))
+        );
+        assert_eq!(keymaps.editor.lookup(&[Key::Ctrl('f')]), Lookup::Undefined);
+        assert_eq!(keymaps.editor.lookup(&[Key::Ctrl('t')]), Lookup::Prefix);
+        assert_eq!(
+            keymaps.prompt.lookup(&[Key::Esc]),
+            Lookup::Command(String::from("cancel"))
+        );
+
+        let mut config = KeymapConfig::default();
+        config.prompt.insert("C-q".into(), "save_file".into());
+        let e = Keymaps::new(&config).err().unwrap();
+        assert_eq!(e, "keymap.prompt:C-q:unknown command save_file");
+        let mut config = KeymapConfig::default();
+        config.editor.insert("C-".into(), "undo".into());
+        let e = Keymaps::new(&config).err().unwrap();
+        assert_eq!(e, "keymap.editor:C-:invalid key");
+    }
+    #[test]
+    fn key_names() {
+        assert_eq!(parse_key("C-t"), Some(Key::Ctrl('t')));
+        assert_eq!(parse_key("M-x"), Some(Key::Alt('x')));
+        assert_eq!(parse_key("F5"), Some(Key::F(5)));
+        assert_eq!(parse_key("PageUp"), Some(Key::PageUp));
+        assert_eq!(parse_key("a"), Some(Key::Char('a')));
+        assert_eq!(parse_key("foo"), None);
+        assert_eq!(parse_keys(""), None);
+        let keys = "C-x C-s M-% F4 Enter SPC C-SPC Up C-Right";
+        assert_eq!(keys_name(&parse_keys(keys).unwrap()), keys);
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+
+const KILL_RING_MAX: usize = 60;
+
+/// Ring of killed(cut or copied) texts. The newest text is at the front.
+#[derive(Default)]
+pub struct KillRing {
+    ring: VecDeque<String>,
+    yank_index: usize, // index of the text which was yanked last
+}
+
+impl KillRing {
+    pub fn new() -> Self {
+        KillRing {
+            ring: VecDeque::new(),
+            yank_index: 0,
+        }
+    }
+    /// add a new killed text. The oldest text is dropped when the ring is full.
+    pub fn push(&mut self, text: &str) {
+        self.ring.push_front(text.to_string());
+        self.ring.truncate(KILL_RING_MAX);
+        self.yank_index = 0;
+    }
+    /// append to the newest text, for consecutive kills.
+    pub fn append(&mut self, text: &str) {
+        match self.ring.front_mut() {
+            Some(front) => front.push_str(text),
+            None => self.push(text),
+        }
+        self.yank_index = 0;
+    }
+    /// the newest text.
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_index = 0;
+        self.ring.front().map(|s| s.as_str())
+    }
+    /// the text older than the one yanked last. Cycles to the newest after the oldest.
+    pub fn yank_pop(&mut self) -> Option<&str> {
+        if self.ring.is_empty() {
+            return None;
+        }
+        self.yank_index = (self.yank_index + 1) % self.ring.len();
+        self.ring.get(self.yank_index).map(|s| s.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yank_pop_cycles() {
+        let mut ring = KillRing::new();
+        assert_eq!(ring.yank(), None);
+        ring.push("a");
+        ring.push("b");
+        ring.push("c");
+        assert_eq!(ring.yank(), Some("c"));
+        assert_eq!(ring.yank_pop(), Some("b"));
+        assert_eq!(ring.yank_pop(), Some("a"));
+        assert_eq!(ring.yank_pop(), Some("c"));
+        assert_eq!(ring.yank(), Some("c"));
+    }
+    #[test]
+    fn append_to_newest() {
+        let mut ring = KillRing::new();
+        ring.append("a");
+        ring.append("\nb");
+        assert_eq!(ring.yank(), Some("a\nb"));
+    }
+    #[test]
+    fn ring_is_limited() {
+        let mut ring = KillRing::new();
+        for i in 0..KILL_RING_MAX + 10 {
+            ring.push(&i.to_string());
+        }
+        assert_eq!(ring.ring.len(), KILL_RING_MAX);
+    }
+}
@@ -5,11 +5,33 @@ mod screen;
 mod statusbar;
 mod macrocommand;
 mod prompt;
+mod text;
+mod undo;
+mod killring;
+mod clipboard;
+mod vscreen;
+mod script;
+mod config;
+mod keymap;
+mod vi;
+mod buffers;
+mod windows;
 
 pub use editbuffer::EditBuffer;
 pub use editor::Editor;
 pub use window::Window;
-pub use screen::Screen;
+pub use screen::{Layout, Screen};
 pub use statusbar::StatusBar;
-pub use macrocommand::MacroCommand;
+pub use macrocommand::{Command, MacroCommand, COMMANDS};
 pub use prompt::Prompt;
+pub use text::Text;
+pub use undo::{Edit, UndoHistory};
+pub use killring::KillRing;
+pub use clipboard::Clipboard;
+pub use vscreen::{Cell, VirtualScreen};
+pub use script::{ScriptEngine, ScriptOutput};
+pub use config::{ClipboardConfig, Colors, Config, KeymapConfig, LineNumbers, Wrap};
+pub use keymap::{key_name, keys_name, parse_key, parse_keys, Keymap, Keymaps, Lookup};
+pub use vi::{Vi, ViAction, ViMode};
+pub use buffers::{buffer_name, BufferList};
+pub use windows::{EditWindow, Rect, View, Windows};
@@ -1,9 +1,145 @@
 use serde::{Deserialize, Serialize};
+use termion::event::Key;
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct MacroCommand {
     pub name: String,
+    #[serde(default)]
     pub arg: usize,
+    #[serde(default)]
     pub argstr: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub argstr2: String, // second string argument, e.g. the replacement of `replace_all`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<MacroCommand>, // body of `repeat`, `while` and `if`
+    #[serde(default, rename = "else", skip_serializing_if = "Vec::is_empty")]
+    pub else_commands: Vec<MacroCommand>, // run by `if` when the condition is false
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub until_break: bool, // `repeat` runs until `break` instead of `arg` times
 }
 
+impl MacroCommand {
+    pub fn new(name: &str, argstr: &str) -> Self {
+        MacroCommand {
+            name: name.to_string(),
+            arg: 1,
+            argstr: argstr.to_string(),
+            argstr2: String::new(),
+            commands: vec![],
+            else_commands: vec![],
+            until_break: false,
+        }
+    }
+    /// how many times the command is executed. `arg` is the count of repeatable commands.
+    pub fn count(&self) -> usize {
+        match find_command(&self.name) {
+            Some(command) if command.repeat => self.arg.max(1),
+            _ => 1,
+        }
+    }
+}
+
+/// Editing command, available both as a key binding and in scripts.
+pub struct Command {
+    pub name: &'static str,
+    pub keys: &'static [Key], // default key bindings
+    pub repeat: bool,         // `arg` is the repeat count
+}
+
+const fn command(name: &'static str, keys: &'static [Key], repeat: bool) -> Command {
+    Command { name, keys, repeat }
+}
+
+/// All editing commands. Commands which need a prompt (search, ...) are in the keymap of the editor.
+pub const COMMANDS: &[Command] = &[
+    command("new_buffer", &[], false),
+    command("set_file_name", &[], false), // argstr: file name
+    command("open_file", &[], false),     // argstr: file name
+    command("save_file_as", &[], false),  // argstr: file name
+    command("save_file", &[], false),
+    command("find_file", &[], false), // argstr: file name. Opens it in a new buffer
+    command("next_buffer", &[], false),
+    command("previous_buffer", &[], false),
+    command("switch_buffer", &[], false), // argstr: buffer name
+    command("close_buffer", &[], false),  // closes even if modified
+    command("split_window", &[], false),  // above and below
+    command("split_window_vertically", &[], false), // side by side
+    command("other_window", &[], true),
+    command("delete_window", &[], false),
+    command("delete_other_windows", &[], false),
+    command("enlarge_window", &[], true), // by a row
+    command("shrink_window", &[], true),
+    command("enlarge_window_horizontally", &[], true), // by a column
+    command("shrink_window_horizontally", &[], true),
+    command("cursor_up", &[Key::Up], true),
+    command("cursor_down", &[Key::Down], true),
+    command("cursor_left", &[Key::Left], true),
+    command("cursor_right", &[Key::Right], true),
+    command("page_up", &[Key::PageUp], true),
+    command("page_down", &[Key::PageDown], true),
+    command("line_begin", &[Key::Home], false),
+    command("line_end", &[Key::End], false),
+    command("buffer_begin", &[Key::Alt('<')], false),
+    command("buffer_end", &[Key::Alt('>')], false),
+    command("word_forward", &[Key::CtrlRight, Key::Alt('f')], true),
+    command("word_backward", &[Key::CtrlLeft, Key::Alt('b')], true),
+    command("paragraph_forward", &[Key::Alt('}')], true),
+    command("paragraph_backward", &[Key::Alt('{')], true),
+    command("goto_line", &[], false), // arg: line number, 1 origin. Or argstr: "LINE:COL", "+N", "-N"
+    command("insert_char", &[], true), // argstr: the char. Also bound to every printable key
+    command("insert_string", &[], true), // argstr: the text
+    command("newline", &[Key::Char('\n')], true),
+    command("delete_char", &[Key::Delete], true),
+    command("backspace", &[Key::Backspace], true),
+    command("toggle_insert_mode", &[Key::Insert], false),
+    command("undo", &[Key::Ctrl('z')], true),
+    command("redo", &[Key::Ctrl('y')], true),
+    command("replace_all", &[], false), // argstr: regexp, argstr2: replacement
+    command("set_mark", &[Key::Null, Key::Ctrl('@')], false),
+    command("clear_mark", &[Key::Ctrl('g')], false),
+    command("kill_region", &[Key::Ctrl('w')], false),
+    command("copy_region", &[Key::Alt('w')], false),
+    command("kill_line", &[Key::Ctrl('k')], true),
+    command("yank", &[Key::Ctrl('v')], true),
+    command("yank_pop", &[Key::Alt('y')], true),
+    command("set_register", &[], false), // argstr: register, argstr2: text
+    command("copy_to_register", &[], false), // argstr: register. The region, or the current line
+    command("insert_register", &[], true), // argstr: register
+    command("script", &[], false),       // argstr: Rhai code
+    command("load_script", &[], false),  // argstr: Rhai script file
+    command("input_keys", &[], false),   // argstr: keys such as "C-f a Enter", input in the mode
+];
+
+pub fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_commands_and_defaults() {
+        let json = r#"[
+            {"name": "repeat", "arg": 2, "commands": [{"name": "newline"}]},
+            {"name": "if", "argstr": "eof", "else": [{"name": "break"}]}
+        ]"#;
+        let script: Vec<MacroCommand> = serde_json::from_str(json).unwrap();
+        assert_eq!(script[0].commands[0].name, "newline");
+        assert_eq!(script[0].commands[0].arg, 0);
+        assert_eq!(script[1].else_commands[0].name, "break");
+        let json = serde_json::to_string(&MacroCommand::new("newline", "")).unwrap();
+        assert_eq!(json, r#"{"name":"newline","arg":1,"argstr":""}"#);
+    }
+    #[test]
+    fn arg_is_repeat_count() {
+        let mut cmd = MacroCommand::new("cursor_down", "");
+        cmd.arg = 3;
+        assert_eq!(cmd.count(), 3);
+        cmd.arg = 0;
+        assert_eq!(cmd.count(), 1);
+        let mut cmd = MacroCommand::new("goto_line", "");
+        cmd.arg = 3;
+        assert_eq!(cmd.count(), 1);
+    }
+}
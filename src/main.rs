@@ -6,7 +6,10 @@ use termion::*;
 use ked::*;
 
 fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} [options] FILE", program);
+    let brief = format!(
+        "Usage: {} [options] [+LINE] FILE[:LINE[:COL]] [FILE...]",
+        program
+    );
     print!("{}", opts.usage(&brief));
 }
 
@@ -18,41 +21,171 @@ fn main() {
     opts.optflag("h", "help", "print this help");
     opts.optflag("d", "debug", "debug mode");
     opts.optopt("s", "script", "run script", "FILE");
+    opts.optopt(
+        "c",
+        "config",
+        "read settings from FILE instead of $XDG_CONFIG_HOME/ked/config",
+        "FILE",
+    );
+    opts.optflag(
+        "",
+        "headless",
+        "run script without terminal (default if stdout is not a terminal)",
+    );
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(f) => panic!(f.to_string()),
+        Err(f) => {
+            eprintln!("{}", f);
+            print_usage(&program, opts);
+            std::process::exit(1);
+        }
     };
     if matches.opt_present("h") {
         print_usage(&program, opts);
         std::process::exit(0);
     }
-    if let Ok((width, height)) = terminal_size() {
-        let screen = Screen {
-            width: width,
-            height: height,
+    let headless = matches.opt_present("headless") || !is_tty(&std::io::stdout());
+    if matches.opt_present("s") {
+        let script_file = matches.opt_str("s").unwrap();
+        let script = match load_script(&script_file) {
+            Ok(script) => script,
+            Err(e) => {
+                eprintln!("{}:{}", script_file, e);
+                std::process::exit(1);
+            }
         };
-        let editor_win = Window::new(1, 1, screen.width, screen.height - 2, screen);
-        let status_win = Window::new(1, height - 1, screen.width, 1, screen);
-        let prompt_win = Window::new(1, height, screen.width, 1, screen);
-        let status_bar = StatusBar::new(status_win);
-        let prompt_box = Prompt::new(prompt_win);
-        let mut editor = Editor::new(editor_win, status_bar, prompt_box);
-
-        if matches.opt_present("s") {
-            let script_file = matches.opt_str("s").unwrap();
-            let reader = std::io::BufReader::new(std::fs::File::open(script_file).unwrap());
-            let s: Vec<MacroCommand> = serde_json::from_reader(reader).unwrap();
-            editor.run_script(&s);
-        } else if matches.free.is_empty() {
-            editor.run_editor_with_new_buffer(matches.opt_present("d"));
+        // scripts are reproducible: user and project settings apply only by --config.
+        let config = match matches.opt_str("c") {
+            Some(_) => load_config(&matches, None),
+            None => Config::default(),
+        };
+        let result = if headless {
+            new_editor(
+                Screen {
+                    width: 80,
+                    height: 24,
+                },
+                &config,
+            )
+            .run_script_headless(&script)
         } else {
-            let input_file_name = matches.free[0].clone();
+            match terminal_size() {
+                Ok((width, height)) => {
+                    new_editor(Screen { width, height }, &config).run_script(&script)
+                }
+                Err(e) => Err(format!("cannot get terminal size:{}", e)),
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("{}:{}", script_file, e);
+            std::process::exit(1);
+        }
+    } else if let Ok((width, height)) = terminal_size() {
+        let (files, position) = files_and_position(&matches.free);
+        let config = load_config(&matches, files.first().map(String::as_str));
+        let mut editor = new_editor(Screen { width, height }, &config);
+        if let Some(position) = position {
+            editor.set_start_position(&position);
+        }
+        // the other files are opened in the buffer list.
+        for file in files.iter().skip(1) {
+            if let Err(e) = editor.add_buffer(file) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        if let Some(input_file_name) = files.first().cloned() {
             if Path::new(&input_file_name).exists() {
                 editor.run_editor_with_file(&input_file_name, matches.opt_present("d"));
             } else {
                 editor.run_editor_with_new_file(&input_file_name, matches.opt_present("d"));
             }
+        } else {
+            editor.run_editor_with_new_buffer(matches.opt_present("d"));
+        }
+    }
+}
+
+fn new_editor(screen: Screen, config: &Config) -> Editor {
+    let layout = screen.layout();
+    let status_bar = StatusBar::new(layout.status);
+    let prompt_box = Prompt::new(layout.prompt);
+    let mut editor = Editor::new(layout.editor, status_bar, prompt_box);
+    editor.set_config(config);
+    editor
+}
+
+/// the files to edit and the position to start in the first one, from `+LINE FILE` or
+/// `FILE:LINE[:COL]` as printed by compilers and `grep -n`.
+fn files_and_position(args: &[String]) -> (Vec<String>, Option<String>) {
+    let mut position = None;
+    let mut files = vec![];
+    for arg in args {
+        match arg.strip_prefix('+') {
+            Some(line) if files.is_empty() && is_position(line) => {
+                position = Some(line.to_string())
+            }
+            _ => {
+                let (file, file_position) = split_position(arg);
+                if files.is_empty() && file_position.is_some() {
+                    position = file_position;
+                }
+                files.push(file);
+            }
+        }
+    }
+    (files, position)
+}
+
+/// "FILE:LINE[:COL]" to the file and "LINE[:COL]", unless the file named so exists.
+fn split_position(arg: &str) -> (String, Option<String>) {
+    if Path::new(arg).exists() {
+        return (arg.to_string(), None);
+    }
+    let mut name = arg;
+    let mut numbers = vec![];
+    while numbers.len() < 2 {
+        match name.rsplit_once(':') {
+            Some((head, n)) if is_position(n) => {
+                numbers.insert(0, n);
+                name = head;
+            }
+            _ => break,
+        }
+    }
+    if numbers.is_empty() || name.is_empty() {
+        (arg.to_string(), None)
+    } else {
+        (name.to_string(), Some(numbers.join(":")))
+    }
+}
+
+fn is_position(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_digit()) && s.chars().all(|c| c.is_ascii_digit() || c == ':')
+}
+
+/// settings for the file to be edited. Exits on an invalid config file.
+fn load_config(matches: &getopts::Matches, file: Option<&str>) -> Config {
+    let current_dir = env::current_dir().unwrap_or_default();
+    // the project config is searched from the directory of the file.
+    let dir = match file {
+        Some(file) => current_dir
+            .join(file)
+            .parent()
+            .map_or(current_dir.clone(), Path::to_path_buf),
+        None => current_dir,
+    };
+    match Config::load(matches.opt_str("c").as_deref(), &dir) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("config error:{}", e);
+            std::process::exit(1);
         }
     }
 }
+
+fn load_script(script_file: &str) -> Result<Vec<MacroCommand>, String> {
+    let file = std::fs::File::open(script_file).map_err(|e| e.to_string())?;
+    serde_json::from_reader(std::io::BufReader::new(file)).map_err(|e| e.to_string())
+}
@@ -3,6 +3,8 @@ use termion::*;
 
 use std::str;
 
+use unicode_width::UnicodeWidthStr;
+
 use crate::*;
 
 pub struct Prompt {
@@ -17,11 +19,11 @@ impl Prompt {
         Prompt {
             prompt: String::from(""),
             result: String::from(""),
-            window: window,
+            window,
             cur_x: 0,
         }
     }
-    pub fn clear(&mut self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
+    pub fn clear<W: Write>(&mut self, output: &mut W) {
         self.result = String::from("");
         for _ in 0..self.window.width() {
             self.result.push(' ');
@@ -42,25 +44,35 @@ impl Prompt {
     }
     pub fn set_prompt(&mut self, prompt_str: &str) {
         self.prompt = String::from(prompt_str);
-        self.cur_x = self.prompt.len();
-        self.window.set_cur_x(self.cur_x as u16);
+        self.update_cur_x();
     }
     pub fn backspace(&mut self) {
         self.result.pop();
+        self.update_cur_x();
+    }
+    /// replace the input, e.g. by completion.
+    pub fn set_result(&mut self, result: &str) {
+        self.result = String::from(result);
+        self.update_cur_x();
     }
     pub fn push(&mut self, c: char) {
         self.result.push(c);
-        self.cur_x += 1;
-        let cur_x = self.window.cur_x() + 1;
-        self.window.set_cur_x(cur_x);
+        self.update_cur_x();
     }
-    pub fn redraw(&mut self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
+    /// cursor is placed after the input, counting display width.
+    fn update_cur_x(&mut self) {
+        self.cur_x = self.prompt.width() + self.result.width();
+        self.window.set_cur_x(self.cur_x as u16);
+    }
+    pub fn redraw<W: Write>(&mut self, output: &mut W) {
+        let padding = (self.window.width() as usize).saturating_sub(self.cur_x);
         write!(
             output,
-            "{}{}{}{}{}",
+            "{}{}{}{}{}{}",
             cursor::Goto(self.window.x(), self.window.y()),
             self.prompt,
             self.result,
+            " ".repeat(padding),
             cursor::Goto(self.window.scr_cur_x(), self.window.scr_cur_y()),
             cursor::Show,
         )
@@ -71,6 +83,10 @@ impl Prompt {
         eprintln!("prompt.rs:result:{}", self.result);
         &self.result
     }
+    pub fn set_window(&mut self, window: Window) {
+        self.window = window;
+        self.update_cur_x();
+    }
     pub fn window(&mut self) -> &mut Window {
         &mut self.window
     }
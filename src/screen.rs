@@ -1,6 +1,87 @@
+use crate::*;
+
 /// Get terminal information and hold.
 #[derive(Clone, Copy)]
 pub struct Screen {
     pub width: u16,
     pub height: u16,
 }
+
+/// Windows on the screen: editor on the top, then one line each for status bar and prompt.
+pub struct Layout {
+    pub editor: Window,
+    pub status: Window,
+    pub prompt: Window,
+}
+
+impl Screen {
+    /// split the screen into windows.
+    /// Even on a tiny screen every window has at least one column and one line on the screen.
+    /// Below 3 lines, the status bar and the prompt share the last line.
+    pub fn layout(self) -> Layout {
+        let screen = Screen {
+            width: self.width.max(1),
+            height: self.height.max(1),
+        };
+        let editor_height = screen.height.saturating_sub(2).max(1);
+        let row = |y: u16| y.min(screen.height);
+        Layout {
+            editor: Window::new(1, 1, screen.width, editor_height, screen),
+            status: Window::new(1, row(editor_height + 1), screen.width, 1, screen),
+            prompt: Window::new(1, row(editor_height + 2), screen.width, 1, screen),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_80x24() {
+        let screen = Screen {
+            width: 80,
+            height: 24,
+        };
+        let layout = screen.layout();
+        assert_eq!((layout.editor.y(), layout.editor.height()), (1, 22));
+        assert_eq!(layout.editor.width(), 80);
+        assert_eq!((layout.status.y(), layout.status.height()), (23, 1));
+        assert_eq!((layout.prompt.y(), layout.prompt.height()), (24, 1));
+    }
+    #[test]
+    fn layout_10x3() {
+        let screen = Screen {
+            width: 10,
+            height: 3,
+        };
+        let layout = screen.layout();
+        assert_eq!((layout.editor.y(), layout.editor.height()), (1, 1));
+        assert_eq!((layout.status.y(), layout.prompt.y()), (2, 3));
+        assert_eq!(layout.status.width(), 10);
+    }
+    #[test]
+    fn layout_smaller_than_3_lines() {
+        let screen = Screen {
+            width: 0,
+            height: 1,
+        };
+        let layout = screen.layout();
+        assert_eq!((layout.editor.y(), layout.editor.height()), (1, 1));
+        assert_eq!(layout.editor.width(), 1);
+        assert_eq!((layout.status.y(), layout.prompt.y()), (1, 1));
+        let screen = Screen {
+            width: 10,
+            height: 2,
+        };
+        let layout = screen.layout();
+        assert_eq!((layout.editor.y(), layout.editor.height()), (1, 1));
+        assert_eq!((layout.status.y(), layout.prompt.y()), (2, 2));
+        let screen = Screen {
+            width: 10,
+            height: 0,
+        };
+        let layout = screen.layout();
+        assert_eq!((layout.status.y(), layout.prompt.y()), (1, 1));
+    }
+}
@@ -0,0 +1,316 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST, INT};
+use termion::event::Key;
+
+use crate::config::config_dir;
+use crate::keymap::parse_keys;
+use crate::*;
+
+/// State shared between the editor and the functions called from scripts.
+#[derive(Default)]
+struct Context {
+    buf: Option<EditBuffer>, // the buffer, lent to the script while it runs
+    changed: bool,
+    message: Option<String>,
+    prompt: Option<(String, String)>, // label and callback function requested by `prompt()`
+    bindings: Vec<(Vec<Key>, String)>, // keys bound to script functions by `bind_key()`
+}
+
+/// What the editor should do after a script ran.
+#[derive(Debug, Default, PartialEq)]
+pub struct ScriptOutput {
+    pub changed: bool,                     // the buffer was modified
+    pub message: Option<String>,           // message to be shown
+    pub prompt: Option<(String, String)>,  // ask the user, and call the function with the input
+    pub bindings: Vec<(Vec<Key>, String)>, // new key bindings to script functions
+}
+
+/// Embedded Rhai interpreter for user extensions.
+///
+/// Functions available to scripts:
+/// - `text()`, `line(y)`, `line_count()`: read the buffer. `y` is 0 origin.
+/// - `cursor_x()`, `cursor_y()`, `move_to(x, y)`: the cursor.
+/// - `insert(s)`, `delete(n)`: insert at the cursor, delete `n` chars after the cursor.
+/// - `file_name()`
+/// - `set_marker(y, "c")`, `clear_markers()`: a marker in the gutter of the line, "" to remove it.
+/// - `message(s)`, `print(s)`: show a message.
+/// - `prompt(label, "function")`: ask the user, and call the function with the input.
+/// - `bind_key("C-c t", "function")`: call the function by the key sequence.
+pub struct ScriptEngine {
+    engine: Engine,
+    ast: AST, // functions defined by the loaded scripts
+    scope: Scope<'static>,
+    context: Rc<RefCell<Context>>,
+}
+
+/// run `f` with the lent buffer, or return the default if no buffer is lent.
+fn with_buf<T: Default>(context: &Rc<RefCell<Context>>, f: impl FnOnce(&mut EditBuffer) -> T) -> T {
+    context.borrow_mut().buf.as_mut().map(f).unwrap_or_default()
+}
+
+impl ScriptEngine {
+    pub fn new() -> Self {
+        let context = Rc::new(RefCell::new(Context::default()));
+        let mut engine = Engine::new();
+        // a script in an infinite loop must not freeze the editor.
+        engine.set_max_operations(10_000_000);
+
+        let c = context.clone();
+        engine.register_fn("text", move || with_buf(&c, |buf| buf.text().to_string()));
+        let c = context.clone();
+        engine.register_fn("line", move |y: INT| {
+            with_buf(&c, |buf| {
+                let text = buf.text();
+                if y >= 0 && (y as usize) < text.len_lines() {
+                    text.line(y as usize)
+                } else {
+                    String::new()
+                }
+            })
+        });
+        let c = context.clone();
+        engine.register_fn("line_count", move || {
+            with_buf(&c, |buf| buf.text().len_lines() as INT)
+        });
+        let c = context.clone();
+        engine.register_fn("cursor_x", move || with_buf(&c, |buf| buf.cur_x() as INT));
+        let c = context.clone();
+        engine.register_fn("cursor_y", move || with_buf(&c, |buf| buf.cur_y() as INT));
+        let c = context.clone();
+        engine.register_fn("move_to", move |x: INT, y: INT| {
+            with_buf(&c, |buf| {
+                let last = buf.text().len_lines().saturating_sub(1);
+                buf.goto(x.max(0) as usize, (y.max(0) as usize).min(last));
+            })
+        });
+        let c = context.clone();
+        engine.register_fn("insert", move |s: &str| {
+            with_buf(&c, |buf| buf.insert_str(s));
+            c.borrow_mut().changed = true;
+        });
+        let c = context.clone();
+        engine.register_fn("delete", move |n: INT| {
+            with_buf(&c, |buf| {
+                let pos = buf.cursor_pos();
+                let end = (pos + n.max(0) as usize).min(buf.text().len_chars());
+                buf.replace_range(pos, end, "");
+            });
+            c.borrow_mut().changed = true;
+        });
+        let c = context.clone();
+        engine.register_fn("file_name", move || {
+            with_buf(&c, |buf| buf.file_name().to_string())
+        });
+        let c = context.clone();
+        engine.register_fn("set_marker", move |y: INT, marker: &str| {
+            with_buf(&c, |buf| {
+                buf.set_marker(y.max(0) as usize, marker.chars().next())
+            });
+        });
+        let c = context.clone();
+        engine.register_fn("clear_markers", move || {
+            with_buf(&c, |buf| buf.clear_markers())
+        });
+        let c = context.clone();
+        engine.register_fn("message", move |s: &str| {
+            c.borrow_mut().message = Some(s.to_string());
+        });
+        let c = context.clone();
+        engine.on_print(move |s| {
+            c.borrow_mut().message = Some(s.to_string());
+        });
+        let c = context.clone();
+        engine.register_fn("prompt", move |label: &str, callback: &str| {
+            c.borrow_mut().prompt = Some((label.to_string(), callback.to_string()));
+        });
+        let c = context.clone();
+        engine.register_fn(
+            "bind_key",
+            move |key: &str, function: &str| -> Result<(), Box<EvalAltResult>> {
+                let keys = parse_keys(key).ok_or(format!("invalid key:{}", key))?;
+                c.borrow_mut().bindings.push((keys, function.to_string()));
+                Ok(())
+            },
+        );
+
+        ScriptEngine {
+            engine,
+            ast: AST::empty(),
+            scope: Scope::new(),
+            context,
+        }
+    }
+    /// path of the init script: `$XDG_CONFIG_HOME/ked/init.rhai`
+    pub fn init_file() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("init.rhai"))
+    }
+    /// run the script file, and keep its functions for later calls.
+    pub fn load_file(
+        &mut self,
+        file_name: &str,
+        buf: &mut EditBuffer,
+    ) -> Result<ScriptOutput, String> {
+        let source = fs::read_to_string(file_name).map_err(|e| format!("{}:{}", file_name, e))?;
+        let ast = self
+            .engine
+            .compile(&source)
+            .map_err(|e| format!("{}:{}", file_name, e))?;
+        let output = self.run(buf, |engine, scope| engine.eval_ast_with_scope(scope, &ast))?;
+        self.ast = self.ast.merge(&ast.clone_functions_only());
+        Ok(output)
+    }
+    /// evaluate the code, e.g. input from the command prompt.
+    /// A value other than `()` is shown as a message.
+    pub fn eval(&mut self, code: &str, buf: &mut EditBuffer) -> Result<ScriptOutput, String> {
+        let ast = self
+            .ast
+            .merge(&self.engine.compile(code).map_err(|e| e.to_string())?);
+        self.run(buf, |engine, scope| engine.eval_ast_with_scope(scope, &ast))
+    }
+    /// call the script function with the argument, e.g. by a key binding.
+    pub fn call(
+        &mut self,
+        function: &str,
+        arg: Option<&str>,
+        buf: &mut EditBuffer,
+    ) -> Result<ScriptOutput, String> {
+        let ast = self.ast.clone();
+        self.run(buf, |engine, scope| match arg {
+            Some(arg) => engine.call_fn(scope, &ast, function, (arg.to_string(),)),
+            None => engine.call_fn(scope, &ast, function, ()),
+        })
+    }
+    /// lend the buffer to the script functions while `f` runs.
+    fn run<F>(&mut self, buf: &mut EditBuffer, f: F) -> Result<ScriptOutput, String>
+    where
+        F: FnOnce(&Engine, &mut Scope<'static>) -> Result<Dynamic, Box<EvalAltResult>>,
+    {
+        let placeholder = EditBuffer::new(buf.window().clone());
+        self.context.borrow_mut().buf = Some(std::mem::replace(buf, placeholder));
+        let result = f(&self.engine, &mut self.scope);
+        let mut context = self.context.borrow_mut();
+        *buf = context.buf.take().unwrap();
+        let mut output = ScriptOutput {
+            changed: std::mem::replace(&mut context.changed, false),
+            message: context.message.take(),
+            prompt: context.prompt.take(),
+            bindings: std::mem::take(&mut context.bindings),
+        };
+        let value = result.map_err(|e| e.to_string())?;
+        if !value.is_unit() {
+            output.message = Some(value.to_string());
+        }
+        Ok(output)
+    }
+}
+
+impl Default for ScriptEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn buffer() -> EditBuffer {
+        let layout = Screen {
+            width: 80,
+            height: 24,
+        }
+        .layout();
+        let mut buf = EditBuffer::new(layout.editor);
+        buf.new_buffer();
+        buf
+    }
+
+    #[test]
+    fn edit_buffer_from_script() {
+        let mut script = ScriptEngine::new();
+        let mut buf = buffer();
+        let output = script
+            .eval(
+                r#"insert("abc\ndef"); move_to(1, 0); delete(1); line(0)"#,
+                &mut buf,
+            )
+            .unwrap();
+        assert!(output.changed);
+        assert_eq!(output.message, Some(String::from("ac")));
+        assert_eq!(buf.text().to_string(), "ac\ndef");
+        assert_eq!(buf.cursor_pos(), 1);
+    }
+    #[test]
+    fn markers_from_script() {
+        let mut script = ScriptEngine::new();
+        let mut buf = buffer();
+        let mut screen = VirtualScreen::new(80, 24);
+        script
+            .eval(r#"insert("a\nb"); set_marker(1, "!")"#, &mut buf)
+            .unwrap();
+        buf.redraw(&mut screen);
+        assert_eq!((screen.row(1), screen.row(2)), (" a".into(), "!b".into()));
+        script.eval("clear_markers()", &mut buf).unwrap();
+        buf.redraw(&mut screen);
+        assert_eq!(screen.row(2), "b");
+    }
+    #[test]
+    fn functions_are_kept() {
+        let mut script = ScriptEngine::new();
+        let mut buf = buffer();
+        let file_name = env::temp_dir().join("ked_test_script_init.rhai");
+        fs::write(
+            &file_name,
+            r#"
+            fn upcase_line() {
+                let s = line(cursor_y());
+                move_to(0, cursor_y());
+                delete(s.len());
+                s.make_upper();
+                insert(s);
+            }
+            fn ask() { prompt("Name: ", "hello"); }
+            fn hello(name) { message("Hello, " + name); }
+            bind_key("C-c u", "upcase_line");
+            "#,
+        )
+        .unwrap();
+        let output = script
+            .load_file(file_name.to_str().unwrap(), &mut buf)
+            .unwrap();
+        fs::remove_file(&file_name).ok();
+        assert!(!output.changed);
+        assert_eq!(
+            output.bindings,
+            vec![(
+                vec![Key::Ctrl('c'), Key::Char('u')],
+                String::from("upcase_line")
+            )]
+        );
+
+        script.eval(r#"insert("abc")"#, &mut buf).unwrap();
+        script.call("upcase_line", None, &mut buf).unwrap();
+        assert_eq!(buf.text().to_string(), "ABC");
+        let output = script.call("ask", None, &mut buf).unwrap();
+        assert_eq!(
+            output.prompt,
+            Some((String::from("Name: "), String::from("hello")))
+        );
+        let output = script.call("hello", Some("k\"ed"), &mut buf).unwrap();
+        assert_eq!(output.message, Some(String::from("Hello, k\"ed")));
+    }
+    #[test]
+    fn errors_are_returned() {
+        let mut script = ScriptEngine::new();
+        let mut buf = buffer();
+        assert!(script.eval("no_such_function()", &mut buf).is_err());
+        assert!(script.eval("bind_key(\"C-\", \"f\")", &mut buf).is_err());
+        assert!(script.eval("loop {}", &mut buf).is_err());
+        assert_eq!(buf.text().to_string(), "");
+    }
+}
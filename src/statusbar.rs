@@ -3,13 +3,19 @@ use termion::*;
 
 use std::str;
 
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
 use crate::*;
 
+#[derive(Clone)]
 pub struct StatusBar {
     file_name: String,
     insert_mode_flag: bool,
     window: Window,
     changed: bool,
+    colors: Colors,
+    mode_name: Option<&'static str>, // shown instead of Ins/Ovr, such as "NORMAL"
+    buffer_index: (usize, usize),    // index of the displayed buffer, and the number of buffers
 }
 
 impl StatusBar {
@@ -17,35 +23,48 @@ impl StatusBar {
         StatusBar {
             file_name: String::from(""),
             insert_mode_flag: true,
-            window: window,
+            window,
             changed: false,
+            colors: Colors::default(),
+            mode_name: None,
+            buffer_index: (0, 1),
         }
     }
-    pub fn redraw(&mut self, output: &mut termion::raw::RawTerminal<std::io::Stdout>) {
-        let mut bar = String::from("");
-        for _ in 0..self.window.width() {
-            bar.push(' ');
-        }
-        bar.replace_range(0..self.file_name.len(), &self.file_name);
-        bar.replace_range(
-            bar.len() - 5..bar.len() - 4,
-            if self.changed == true { "*" } else { " " },
-        );
-        bar.replace_range(
-            bar.len() - 3..bar.len(),
-            if self.insert_mode_flag == true {
-                "Ins"
-            } else {
-                "Ovr"
-            },
+    pub fn redraw<W: Write>(&mut self, output: &mut W) {
+        let width = self.window.width() as usize;
+        let flags = format!(
+            "{} {}",
+            if self.changed { "*" } else { " " },
+            match self.mode_name {
+                Some(name) => name,
+                None if self.insert_mode_flag => "Ins",
+                None => "Ovr",
+            }
         );
+        let (index, count) = self.buffer_index;
+        let name = if count > 1 {
+            format!("[{}/{}] {}", index + 1, count, self.file_name)
+        } else {
+            self.file_name.clone()
+        };
+        let mut bar = String::new();
+        for c in name.chars() {
+            if bar.width() + c.width().unwrap_or(0) + flags.len() > width {
+                break;
+            }
+            bar.push(c);
+        }
+        bar.push_str(&" ".repeat(width.saturating_sub(bar.width() + flags.len())));
+        bar.push_str(&flags);
+        // on a very narrow screen, flags are truncated too.
+        let bar: String = bar.chars().take(width).collect();
 
         write!(
             output,
             "{}{}{}{}{}{}{}",
             cursor::Goto(self.window.scr_cur_x(), self.window.scr_cur_y()),
-            color::Fg(color::Black),
-            color::Bg(color::White),
+            color::Fg(color::AnsiValue(self.colors.status_fg)),
+            color::Bg(color::AnsiValue(self.colors.status_bg)),
             bar,
             color::Fg(color::White),
             color::Bg(color::Black),
@@ -55,18 +74,32 @@ impl StatusBar {
         output.flush().unwrap();
     }
     pub fn toggle_insert_mode(&mut self) {
-        if self.insert_mode_flag {
-            self.insert_mode_flag = false;
-        } else {
-            self.insert_mode_flag = true;
-        }
+        self.insert_mode_flag = !self.insert_mode_flag;
+    }
+    pub fn set_insert_mode(&mut self, insert_mode: bool) {
+        self.insert_mode_flag = insert_mode;
+    }
+    pub fn set_colors(&mut self, colors: Colors) {
+        self.colors = colors;
+    }
+    pub fn set_mode_name(&mut self, mode_name: Option<&'static str>) {
+        self.mode_name = mode_name;
     }
     pub fn insert_mode_flag(&self) -> bool {
         self.insert_mode_flag
     }
+    pub fn set_window(&mut self, window: Window) {
+        self.window = window;
+    }
+    pub fn window(&mut self) -> &mut Window {
+        &mut self.window
+    }
     pub fn set_file_name(&mut self, file_name: &str) {
         self.file_name = String::from(file_name);
     }
+    pub fn set_buffer_index(&mut self, index: usize, count: usize) {
+        self.buffer_index = (index, count);
+    }
     pub fn set_changed(&mut self, changed: bool) {
         self.changed = changed;
     }
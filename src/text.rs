@@ -0,0 +1,359 @@
+use regex::Regex;
+use ropey::Rope;
+
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+/// Text storage of the edit buffer. Implemented by a rope, so that insert/delete
+/// by char offset is O(log n) even for multi-megabyte files.
+/// Lines are separated by '\n'. The last line has no trailing newline,
+/// so an empty text still has one (blank) line.
+#[derive(Clone, Default)]
+pub struct Text {
+    rope: Rope,
+}
+
+impl Text {
+    pub fn new() -> Self {
+        Text { rope: Rope::new() }
+    }
+    pub fn from_lines<I: Iterator<Item = String>>(lines: I) -> Self {
+        let mut text = String::new();
+        for (i, line) in lines.enumerate() {
+            if i > 0 {
+                text.push('\n');
+            }
+            text.push_str(&line);
+        }
+        Text {
+            rope: Rope::from_str(&text),
+        }
+    }
+    /// write every line terminated by newline.
+    pub fn write_to<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for y in 0..self.len_lines() {
+            writeln!(output, "{}", self.line(y))?;
+        }
+        Ok(())
+    }
+    /// number of lines.
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+    /// number of chars, including newlines.
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+    /// line `y` without the newline.
+    pub fn line(&self, y: usize) -> String {
+        let mut line = self.rope.line(y).to_string();
+        if line.ends_with('\n') {
+            line.pop();
+        }
+        line
+    }
+    /// length of line `y` in chars, not counting the newline.
+    pub fn line_len(&self, y: usize) -> usize {
+        let len = self.rope.line(y).len_chars();
+        if y + 1 < self.len_lines() {
+            len - 1
+        } else {
+            len
+        }
+    }
+    pub fn line_to_char(&self, y: usize) -> usize {
+        self.rope.line_to_char(y)
+    }
+    pub fn char_to_line(&self, pos: usize) -> usize {
+        self.rope.char_to_line(pos)
+    }
+    pub fn char_to_byte(&self, pos: usize) -> usize {
+        self.rope.char_to_byte(pos)
+    }
+    pub fn byte_to_char(&self, byte: usize) -> usize {
+        self.rope.byte_to_char(byte)
+    }
+    /// char offset of the buffer coodinates (x, y).
+    pub fn pos(&self, x: usize, y: usize) -> usize {
+        self.line_to_char(y) + x
+    }
+    /// buffer coodinates (x, y) of the char offset.
+    pub fn xy(&self, pos: usize) -> (usize, usize) {
+        let y = self.char_to_line(pos);
+        (pos - self.line_to_char(y), y)
+    }
+    pub fn char(&self, pos: usize) -> char {
+        self.rope.char(pos)
+    }
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        self.rope.slice(start..end).to_string()
+    }
+    pub fn insert_char(&mut self, pos: usize, ch: char) {
+        self.rope.insert_char(pos, ch);
+    }
+    pub fn insert(&mut self, pos: usize, s: &str) {
+        self.rope.insert(pos, s);
+    }
+    pub fn remove(&mut self, start: usize, end: usize) {
+        self.rope.remove(start..end);
+    }
+    /// char offset of the first match of `pattern` starting at or after `pos`.
+    pub fn find(&self, pattern: &str, pos: usize) -> Option<usize> {
+        if pattern.is_empty() || pos > self.len_chars() {
+            return None;
+        }
+        let (x, y0) = self.xy(pos);
+        for y in y0..self.len_lines() {
+            let line = self.line(y);
+            // search from the column, so that a match overlapping a previous one is found.
+            let start = if y == y0 { char_to_byte(&line, x) } else { 0 };
+            if let Some(i) = line[start..].find(pattern) {
+                return Some(self.line_to_char(y) + line[..start + i].chars().count());
+            }
+        }
+        None
+    }
+    /// char offset of the last match of `pattern` starting before `pos`.
+    pub fn rfind(&self, pattern: &str, pos: usize) -> Option<usize> {
+        if pattern.is_empty() {
+            return None;
+        }
+        let (x, y0) = self.xy(pos.min(self.len_chars()));
+        for y in (0..=y0).rev() {
+            let line = self.line(y);
+            // a match starting before the column ends before this bound.
+            let end = if y < y0 {
+                line.len()
+            } else if x == 0 {
+                continue;
+            } else {
+                let mut end = (char_to_byte(&line, x) + pattern.len() - 1).min(line.len());
+                while !line.is_char_boundary(end) {
+                    end -= 1;
+                }
+                end
+            };
+            if let Some(i) = line[..end].rfind(pattern) {
+                return Some(self.line_to_char(y) + line[..i].chars().count());
+            }
+        }
+        None
+    }
+    /// char offsets (start, end) of the first match of `re` starting at or after `pos`.
+    /// Matches do not span lines.
+    pub fn find_regex(&self, re: &Regex, pos: usize) -> Option<(usize, usize)> {
+        if pos > self.len_chars() {
+            return None;
+        }
+        let (x, y0) = self.xy(pos);
+        for y in y0..self.len_lines() {
+            let line = self.line(y);
+            let start = if y == y0 { char_to_byte(&line, x) } else { 0 };
+            if let Some(m) = re.find_at(&line, start) {
+                let line_pos = self.line_to_char(y);
+                return Some((
+                    line_pos + line[..m.start()].chars().count(),
+                    line_pos + line[..m.end()].chars().count(),
+                ));
+            }
+        }
+        None
+    }
+    /// char offsets (start, end) of the match of `re` starting nearest before `pos`.
+    /// Matches may overlap, e.g. "aba" in "ababa".
+    pub fn rfind_regex(&self, re: &Regex, pos: usize) -> Option<(usize, usize)> {
+        let (x, y0) = self.xy(pos.min(self.len_chars()));
+        for y in (0..=y0).rev() {
+            let line = self.line(y);
+            let end = if y == y0 {
+                char_to_byte(&line, x)
+            } else {
+                line.len() + 1
+            };
+            // step back a char at a time from `end`, for a match starting there.
+            let mut at = end;
+            let mut found = None;
+            while found.is_none() && at > 0 {
+                at -= 1;
+                while !line.is_char_boundary(at) {
+                    at -= 1;
+                }
+                found = re.find_at(&line, at).filter(|m| m.start() < end);
+            }
+            if let Some(m) = found {
+                let line_pos = self.line_to_char(y);
+                return Some((
+                    line_pos + line[..m.start()].chars().count(),
+                    line_pos + line[..m.end()].chars().count(),
+                ));
+            }
+        }
+        None
+    }
+    /// replace the contents of line `y`.
+    pub fn set_line(&mut self, y: usize, s: &str) {
+        let start = self.line_to_char(y);
+        let end = start + self.line_len(y);
+        self.remove(start, end);
+        self.insert(start, s);
+    }
+    /// append a new line at the end of the text.
+    pub fn push_line(&mut self, s: &str) {
+        let end = self.len_chars();
+        self.insert_char(end, '\n');
+        self.insert(end + 1, s);
+    }
+}
+
+/// byte index of the `x`-th char in `line`. `line.len()` if `x` is out of the line.
+pub fn char_to_byte(line: &str, x: usize) -> usize {
+    line.char_indices().nth(x).map_or(line.len(), |(i, _)| i)
+}
+
+/// Kind of characters making a word. A word is a run of characters of the same class,
+/// so that Japanese text is split at changes between kanji, hiragana and katakana.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CharClass {
+    Space, // including newline
+    Word,  // alphanumeric and '_'
+    Punct,
+    Hiragana,
+    Katakana,
+    Kanji,
+    FullWidthPunct, // CJK symbols and punctuation such as '、' and '。'
+}
+
+pub fn char_class(c: char) -> CharClass {
+    match c {
+        c if c.is_whitespace() => CharClass::Space,
+        '\u{3041}'..='\u{309f}' => CharClass::Hiragana,
+        '\u{30a0}'..='\u{30ff}' | '\u{ff66}'..='\u{ff9f}' => CharClass::Katakana,
+        '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' | '々' => {
+            CharClass::Kanji
+        }
+        '\u{3000}'..='\u{303f}' | '\u{ff01}'..='\u{ff0f}' | '\u{ff1a}'..='\u{ff20}' => {
+            CharClass::FullWidthPunct
+        }
+        c if c.is_alphanumeric() || c == '_' => CharClass::Word,
+        _ => CharClass::Punct,
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.rope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_lines_and_line() {
+        let text = Text::from_lines(vec![String::from("abc"), String::from("あいう")].into_iter());
+        assert_eq!(text.len_lines(), 2);
+        assert_eq!(text.line(0), "abc");
+        assert_eq!(text.line(1), "あいう");
+        assert_eq!(text.line_len(0), 3);
+        assert_eq!(text.line_len(1), 3);
+    }
+    #[test]
+    fn char_classes() {
+        assert_eq!(char_class('a'), CharClass::Word);
+        assert_eq!(char_class('\n'), CharClass::Space);
+        assert_eq!(char_class('.'), CharClass::Punct);
+        assert_eq!(char_class('漢'), CharClass::Kanji);
+        assert_eq!(char_class('の'), CharClass::Hiragana);
+        assert_eq!(char_class('カ'), CharClass::Katakana);
+        assert_eq!(char_class('ー'), CharClass::Katakana);
+        assert_eq!(char_class('。'), CharClass::FullWidthPunct);
+        assert_eq!(char_class('Ａ'), CharClass::Word);
+    }
+    #[test]
+    fn empty_text_has_one_line() {
+        let text = Text::new();
+        assert_eq!(text.len_lines(), 1);
+        assert_eq!(text.line_len(0), 0);
+    }
+    #[test]
+    fn index_conversion() {
+        let text = Text::from_lines(vec![String::from("aあ"), String::from("b")].into_iter());
+        assert_eq!(text.pos(0, 1), 3);
+        assert_eq!(text.xy(3), (0, 1));
+        assert_eq!(text.char_to_byte(2), 4);
+        assert_eq!(text.byte_to_char(4), 2);
+    }
+    #[test]
+    fn insert_and_remove_newline() {
+        let mut text = Text::new();
+        text.set_line(0, "abcd");
+        text.insert_char(2, '\n');
+        assert_eq!(text.len_lines(), 2);
+        assert_eq!(text.line(0), "ab");
+        assert_eq!(text.line(1), "cd");
+        text.remove(2, 3);
+        assert_eq!(text.len_lines(), 1);
+        assert_eq!(text.line(0), "abcd");
+    }
+    #[test]
+    fn find_and_rfind_wchar() {
+        let text =
+            Text::from_lines(vec![String::from("あいうあい"), String::from("xあい")].into_iter());
+        assert_eq!(text.find("あい", 0), Some(0));
+        assert_eq!(text.find("あい", 1), Some(3));
+        assert_eq!(text.find("あい", 4), Some(7));
+        assert_eq!(text.find("あい", 8), None);
+        assert_eq!(text.rfind("あい", 7), Some(3));
+        assert_eq!(text.rfind("あい", 3), Some(0));
+        assert_eq!(text.rfind("あい", 0), None);
+    }
+    #[test]
+    fn find_and_rfind_overlapping() {
+        let text = Text::from_lines(vec![String::from("aaaa"), String::from("あああ")].into_iter());
+        assert_eq!(text.find("aa", 1), Some(1));
+        assert_eq!(text.find("aa", 2), Some(2));
+        assert_eq!(text.find("aa", 3), None);
+        assert_eq!(text.find("ああ", 6), Some(6));
+        assert_eq!(text.rfind("aa", 1), Some(0));
+        assert_eq!(text.rfind("aa", 3), Some(2));
+        assert_eq!(text.rfind("ああ", 7), Some(6));
+        assert_eq!(text.rfind("ああ", 6), Some(5));
+        assert_eq!(text.rfind("aa", 5), Some(2));
+        assert_eq!(text.rfind("aa", 0), None);
+    }
+    #[test]
+    fn find_and_rfind_regex() {
+        let text =
+            Text::from_lines(vec![String::from("あa1b22"), String::from("c333")].into_iter());
+        let re = Regex::new("[0-9]+").unwrap();
+        assert_eq!(text.find_regex(&re, 0), Some((2, 3)));
+        assert_eq!(text.find_regex(&re, 3), Some((4, 6)));
+        assert_eq!(text.find_regex(&re, 6), Some((8, 11)));
+        // the match starting nearest before the position.
+        assert_eq!(text.rfind_regex(&re, 8), Some((5, 6)));
+        assert_eq!(text.rfind_regex(&re, 11), Some((10, 11)));
+        assert_eq!(text.rfind_regex(&re, 3), Some((2, 3)));
+        assert_eq!(text.rfind_regex(&re, 2), None);
+    }
+    #[test]
+    fn rfind_regex_overlapping() {
+        let text = Text::from_lines(vec![String::from("ababa")].into_iter());
+        let re = Regex::new("aba").unwrap();
+        assert_eq!(text.rfind_regex(&re, 5), Some((2, 5)));
+        assert_eq!(text.rfind_regex(&re, 2), Some((0, 3)));
+        assert_eq!(text.rfind_regex(&re, 0), None);
+        let re = Regex::new("^a").unwrap();
+        assert_eq!(text.rfind_regex(&re, 5), Some((0, 1)));
+    }
+    #[test]
+    fn write_to_terminates_lines() {
+        let mut text = Text::new();
+        text.set_line(0, "abc");
+        text.push_line("");
+        let mut out: Vec<u8> = vec![];
+        text.write_to(&mut out).unwrap();
+        assert_eq!(out, b"abc\n\n");
+    }
+}
@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::*;
+
+/// One edit operation on the text: `deleted` was removed at `pos` and `inserted` was put there.
+/// An insertion has empty `deleted`, a deletion has empty `inserted`.
+/// Cursor positions are buffer coodinates (x, y), used to restore the cursor on undo/redo.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Edit {
+    pub pos: usize, // char offset in the text
+    pub deleted: String,
+    pub inserted: String,
+    pub cursor_before: (usize, usize),
+    pub cursor_after: (usize, usize),
+}
+
+impl Edit {
+    /// true if `next` directly continues this edit as one typing/deleting sequence.
+    fn continues(&self, next: &Edit) -> bool {
+        if next.inserted.contains('\n') || self.inserted.ends_with('\n') {
+            return false;
+        }
+        if self.deleted.is_empty() && next.deleted.is_empty() {
+            // typed characters
+            !self.inserted.is_empty() && self.pos + self.inserted.chars().count() == next.pos
+        } else if self.inserted.is_empty() && next.inserted.is_empty() {
+            // Delete key pressed repeatedly at the same position
+            self.pos == next.pos && !self.deleted.ends_with('\n')
+        } else {
+            // overwritten characters
+            self.deleted.chars().count() == self.inserted.chars().count()
+                && next.deleted.chars().count() == next.inserted.chars().count()
+                && self.pos + self.inserted.chars().count() == next.pos
+        }
+    }
+    fn merge(&mut self, next: Edit) {
+        self.deleted.push_str(&next.deleted);
+        self.inserted.push_str(&next.inserted);
+        self.cursor_after = next.cursor_after;
+    }
+}
+
+/// Undo history file, saved in the cache directory next to the edited file.
+#[derive(Deserialize, Serialize)]
+struct HistoryFile {
+    path: String,      // absolute path of the edited file
+    content_hash: u64, // hash of the file content when the history was saved
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+/// 64bit FNV-1a hash. Stable between builds, unlike `DefaultHasher`.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Write for Fnv {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for b in buf {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// hash of the text as it is written to the file.
+fn content_hash(text: &Text) -> u64 {
+    let mut hasher = Fnv::new();
+    text.write_to(&mut hasher).unwrap();
+    hasher.0
+}
+
+/// Undo/Redo history of the edit buffer.
+#[derive(Default)]
+pub struct UndoHistory {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    sealed: bool, // next edit starts a new undo step
+}
+
+impl UndoHistory {
+    pub fn new() -> Self {
+        UndoHistory {
+            undo: vec![],
+            redo: vec![],
+            sealed: false,
+        }
+    }
+    /// record an edit. Consecutive typed characters are coalesced into one undo step.
+    pub fn record(&mut self, edit: Edit) {
+        self.redo.clear();
+        if !self.sealed {
+            if let Some(last) = self.undo.last_mut() {
+                if last.continues(&edit) {
+                    last.merge(edit);
+                    return;
+                }
+            }
+        }
+        self.sealed = false;
+        self.undo.push(edit);
+    }
+    /// close the current undo step, the next edit is never coalesced with the previous one.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+    /// take the edit to be reverted.
+    pub fn undo(&mut self) -> Option<Edit> {
+        let edit = self.undo.pop()?;
+        self.redo.push(edit.clone());
+        self.sealed = true;
+        Some(edit)
+    }
+    /// take the edit to be applied again.
+    pub fn redo(&mut self) -> Option<Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push(edit.clone());
+        self.sealed = true;
+        Some(edit)
+    }
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.sealed = false;
+    }
+    /// path of the history file of `file_name`: `$XDG_CACHE_HOME/ked/undo/<hash of absolute path>.json`
+    pub fn history_file(file_name: &str) -> Option<PathBuf> {
+        let cache_dir = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        let path = fs::canonicalize(file_name).ok()?;
+        let mut hasher = Fnv::new();
+        hasher.write_all(path.to_string_lossy().as_bytes()).unwrap();
+        Some(
+            cache_dir
+                .join("ked")
+                .join("undo")
+                .join(format!("{:016x}.json", hasher.0)),
+        )
+    }
+    /// save the history of `file_name` whose content is now `text`.
+    pub fn save(&self, file_name: &str, text: &Text) -> io::Result<()> {
+        let history_file = Self::history_file(file_name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory"))?;
+        let path = fs::canonicalize(file_name)?;
+        self.save_to(&history_file, &path.to_string_lossy(), text)
+    }
+    /// restore the history of `file_name`, only if the history was saved with the same `text`.
+    pub fn load(file_name: &str, text: &Text) -> Option<Self> {
+        let history_file = Self::history_file(file_name)?;
+        let path = fs::canonicalize(file_name).ok()?;
+        Self::load_from(&history_file, &path.to_string_lossy(), text)
+    }
+    pub fn save_to(&self, history_file: &Path, path: &str, text: &Text) -> io::Result<()> {
+        if self.undo.is_empty() && self.redo.is_empty() {
+            if history_file.exists() {
+                fs::remove_file(history_file)?;
+            }
+            return Ok(());
+        }
+        if let Some(dir) = history_file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let history = HistoryFile {
+            path: path.to_string(),
+            content_hash: content_hash(text),
+            undo: self.undo.clone(),
+            redo: self.redo.clone(),
+        };
+        let file = File::create(history_file)?;
+        serde_json::to_writer(file, &history)?;
+        Ok(())
+    }
+    /// load the history. A history which does not belong to `path` and `text`
+    /// (e.g. the file was modified by other program) is discarded without a message.
+    pub fn load_from(history_file: &Path, path: &str, text: &Text) -> Option<Self> {
+        let file = File::open(history_file).ok()?;
+        let history: Result<HistoryFile, _> = serde_json::from_reader(BufReader::new(file));
+        match history {
+            Ok(history) if history.path == path && history.content_hash == content_hash(text) => {
+                Some(UndoHistory {
+                    undo: history.undo,
+                    redo: history.redo,
+                    sealed: true,
+                })
+            }
+            _ => {
+                // silently, as the terminal may be in raw mode.
+                fs::remove_file(history_file).ok();
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(pos: usize, s: &str) -> Edit {
+        Edit {
+            pos,
+            deleted: String::new(),
+            inserted: String::from(s),
+            cursor_before: (pos, 0),
+            cursor_after: (pos + s.chars().count(), 0),
+        }
+    }
+
+    #[test]
+    fn typed_chars_are_coalesced() {
+        let mut history = UndoHistory::new();
+        history.record(insert(0, "a"));
+        history.record(insert(1, "あ"));
+        history.record(insert(2, "c"));
+        let edit = history.undo().unwrap();
+        assert_eq!(edit.inserted, "aあc");
+        assert_eq!(edit.cursor_before, (0, 0));
+        assert_eq!(edit.cursor_after, (3, 0));
+        assert!(history.undo().is_none());
+    }
+    #[test]
+    fn newline_breaks_coalescing() {
+        let mut history = UndoHistory::new();
+        history.record(insert(0, "a"));
+        history.record(insert(1, "\n"));
+        history.record(insert(2, "b"));
+        assert_eq!(history.undo().unwrap().inserted, "b");
+        assert_eq!(history.undo().unwrap().inserted, "\n");
+        assert_eq!(history.undo().unwrap().inserted, "a");
+    }
+    #[test]
+    fn seal_breaks_coalescing() {
+        let mut history = UndoHistory::new();
+        history.record(insert(0, "a"));
+        history.seal();
+        history.record(insert(1, "b"));
+        assert_eq!(history.undo().unwrap().inserted, "b");
+    }
+    #[test]
+    fn save_and_load_history() {
+        let history_file = env::temp_dir().join("ked_undo_test_save_and_load.json");
+        let mut text = Text::new();
+        text.set_line(0, "abc");
+        let mut history = UndoHistory::new();
+        history.record(insert(0, "abc"));
+        history.save_to(&history_file, "/tmp/a.txt", &text).unwrap();
+
+        let mut restored = UndoHistory::load_from(&history_file, "/tmp/a.txt", &text).unwrap();
+        assert_eq!(restored.undo().unwrap().inserted, "abc");
+        fs::remove_file(&history_file).ok();
+    }
+    #[test]
+    fn discard_history_of_modified_file() {
+        let history_file = env::temp_dir().join("ked_undo_test_discard.json");
+        let mut text = Text::new();
+        text.set_line(0, "abc");
+        let mut history = UndoHistory::new();
+        history.record(insert(0, "abc"));
+        history.save_to(&history_file, "/tmp/b.txt", &text).unwrap();
+
+        text.set_line(0, "abcd");
+        assert!(UndoHistory::load_from(&history_file, "/tmp/b.txt", &text).is_none());
+        assert!(!history_file.exists());
+    }
+    #[test]
+    fn record_clears_redo() {
+        let mut history = UndoHistory::new();
+        history.record(insert(0, "a"));
+        history.undo();
+        history.record(insert(0, "b"));
+        assert!(history.redo().is_none());
+    }
+}
@@ -0,0 +1,721 @@
+use termion::event::Key;
+
+use crate::text::{char_class, CharClass};
+use crate::*;
+
+/// Mode of the vi layer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViMode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl ViMode {
+    /// name shown in the status bar.
+    pub fn name(self) -> &'static str {
+        match self {
+            ViMode::Normal => "NORMAL",
+            ViMode::Insert => "INSERT",
+            ViMode::Visual => "VISUAL",
+        }
+    }
+}
+
+/// What the editor should do after a key is given to the vi layer.
+#[derive(Debug, PartialEq)]
+pub enum ViAction {
+    Done,             // the key was handled
+    Changed,          // the key was handled and the buffer was modified
+    PassThrough,      // the key is handled by the keymap of the editor
+    Repeat(Vec<Key>), // input these keys to repeat the last change
+}
+
+/// How the text between the cursor and the target of a motion is taken by an operator.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Range {
+    Exclusive,
+    Inclusive, // including the char at the end
+    Linewise,
+}
+
+/// Optional vi-like modal editing, on top of the `EditBuffer` primitives.
+///
+/// - motions: `h` `j` `k` `l` `w` `b` `e` `0` `^` `$` `gg` `G`
+/// - operators: `d` `c` `y` with a motion, or doubled for lines (`dd`)
+/// - `x` `X` `D` `C` `s` `S` `Y` `p` `P` `u` `C-r` `.`
+/// - `i` `a` `I` `A` `o` `O` to insert mode, `v` to visual mode, `Esc` back to normal mode
+///
+/// A count can be given before commands, motions and operators, e.g. `3dw` or `d3w`.
+/// Keys in insert mode, and other keys such as `C-s`, are handled by the editor.
+pub struct Vi {
+    mode: ViMode,
+    count: Option<usize>,
+    operator: Option<(char, Option<usize>)>, // pending operator and the count before it
+    prefix: Option<char>,                    // 'g' waiting for the next key
+    keys: Vec<Key>,                          // keys of the command being input
+    last_change: Vec<Key>,                   // keys of the last change, repeated by `.`
+    in_change: bool,                         // keys in insert mode are a part of the change
+    register: String,                        // deleted or yanked text
+    linewise: bool,                          // the register holds whole lines
+    visual_start: usize,                     // char offset where the visual mode started
+}
+
+impl Default for Vi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vi {
+    pub fn new() -> Self {
+        Vi {
+            mode: ViMode::Normal,
+            count: None,
+            operator: None,
+            prefix: None,
+            keys: vec![],
+            last_change: vec![],
+            in_change: false,
+            register: String::new(),
+            linewise: false,
+            visual_start: 0,
+        }
+    }
+    pub fn mode(&self) -> ViMode {
+        self.mode
+    }
+    /// a command, an operator or a prefix is waiting for more keys.
+    pub fn is_pending(&self) -> bool {
+        self.count.is_some() || self.operator.is_some() || self.prefix.is_some()
+    }
+    pub fn input(&mut self, key: Key, buf: &mut EditBuffer) -> ViAction {
+        if self.mode == ViMode::Insert {
+            return self.insert_key(key, buf);
+        }
+        self.keys.push(key);
+        let action = self.normal_key(key, buf);
+        if self.mode != ViMode::Insert {
+            stay_on_char(buf);
+        }
+        action
+    }
+    fn insert_key(&mut self, key: Key, buf: &mut EditBuffer) -> ViAction {
+        if self.in_change {
+            self.keys.push(key);
+        }
+        if key != Key::Esc {
+            return ViAction::PassThrough;
+        }
+        self.mode = ViMode::Normal;
+        if self.in_change {
+            self.in_change = false;
+            self.last_change = std::mem::take(&mut self.keys);
+        }
+        if buf.cur_x() > 0 {
+            buf.goto(buf.cur_x() - 1, buf.cur_y());
+        }
+        ViAction::Done
+    }
+    fn normal_key(&mut self, key: Key, buf: &mut EditBuffer) -> ViAction {
+        let c = match key {
+            Key::Char('\n') => 'j',
+            Key::Char(c) => c,
+            Key::Backspace => 'h',
+            Key::Delete => 'x',
+            Key::Esc => {
+                if self.mode == ViMode::Visual {
+                    self.mode = ViMode::Normal;
+                    buf.clear_mark();
+                }
+                return self.done();
+            }
+            Key::Ctrl('r') if !self.is_pending() && self.mode == ViMode::Normal => {
+                let mut changed = false;
+                for _ in 0..self.count.unwrap_or(1) {
+                    changed |= buf.redo();
+                }
+                self.keys.clear();
+                self.reset();
+                return if changed {
+                    ViAction::Changed
+                } else {
+                    ViAction::Done
+                };
+            }
+            _ => {
+                self.keys.clear();
+                self.reset();
+                return ViAction::PassThrough;
+            }
+        };
+        if let Some(prefix) = self.prefix.take() {
+            if prefix == 'g' && c == 'g' {
+                return self.motion_or_operator('g', buf);
+            }
+            return self.done();
+        }
+        if let Some(digit) = c.to_digit(10) {
+            if digit > 0 || self.count.is_some() {
+                self.count = Some(self.count.unwrap_or(0) * 10 + digit as usize);
+                return ViAction::Done;
+            }
+        }
+        if c == 'g' {
+            self.prefix = Some('g');
+            return ViAction::Done;
+        }
+        if self.mode == ViMode::Visual {
+            return self.visual_key(c, buf);
+        }
+        if let Some((op, _)) = self.operator {
+            if c == op {
+                // `dd`, `cc`, `yy`: count lines
+                let n = self.take_count();
+                let text = buf.text();
+                let y = (buf.cur_y() + n - 1).min(text.len_lines() - 1);
+                let end = text.line_to_char(y);
+                return self.operate(op, buf, end, Range::Linewise);
+            }
+            return self.motion_or_operator(c, buf);
+        }
+        let n = self.count.unwrap_or(1);
+        let pos = buf.cursor_pos();
+        let (x, y) = (buf.cur_x(), buf.cur_y());
+        let line_len = buf.text().line_len(y);
+        match c {
+            'd' | 'c' | 'y' => {
+                self.operator = Some((c, self.count.take()));
+                ViAction::Done
+            }
+            'i' => self.insert(false),
+            'a' => {
+                buf.goto((x + 1).min(line_len), y);
+                self.insert(false)
+            }
+            'I' => {
+                buf.goto(
+                    first_non_blank(buf.text(), y) - buf.text().line_to_char(y),
+                    y,
+                );
+                self.insert(false)
+            }
+            'A' => {
+                buf.goto(line_len, y);
+                self.insert(false)
+            }
+            'o' | 'O' => {
+                buf.undo_boundary();
+                let text = buf.text();
+                let at = if c == 'o' {
+                    text.line_to_char(y) + line_len
+                } else {
+                    text.line_to_char(y)
+                };
+                buf.replace_range(at, at, "\n");
+                if c == 'O' {
+                    buf.goto(0, y);
+                }
+                self.insert(true)
+            }
+            'x' | 'X' | 'D' | 'C' | 's' | 'S' | 'Y' => {
+                let (op, motion) = match c {
+                    'x' => ('d', 'l'),
+                    'X' => ('d', 'h'),
+                    'D' => ('d', '$'),
+                    'C' => ('c', '$'),
+                    's' => ('c', 'l'),
+                    'S' => ('c', 'c'),
+                    _ => ('y', 'y'),
+                };
+                if op == 'd' && line_len == 0 {
+                    return self.done();
+                }
+                self.operator = Some((op, None));
+                if motion == op {
+                    return self.normal_key(Key::Char(op), buf);
+                }
+                self.motion_or_operator(motion, buf)
+            }
+            'p' | 'P' => {
+                if self.register.is_empty() {
+                    return self.done();
+                }
+                buf.undo_boundary();
+                self.paste(buf, c == 'p', n);
+                self.changed()
+            }
+            'u' => {
+                let mut changed = false;
+                for _ in 0..n {
+                    changed |= buf.undo();
+                }
+                self.done();
+                if changed {
+                    ViAction::Changed
+                } else {
+                    ViAction::Done
+                }
+            }
+            '.' => {
+                let count = self.count.take();
+                self.done();
+                let mut keys = self.last_change.clone();
+                if let Some(count) = count {
+                    // the new count replaces the count of the change.
+                    let digits = keys
+                        .iter()
+                        .take_while(|key| matches!(key, Key::Char('0'..='9')))
+                        .count();
+                    keys.splice(0..digits, count.to_string().chars().map(Key::Char));
+                }
+                ViAction::Repeat(keys)
+            }
+            'v' => {
+                self.mode = ViMode::Visual;
+                self.visual_start = pos;
+                buf.set_mark();
+                self.done()
+            }
+            _ => self.motion_or_operator(c, buf),
+        }
+    }
+    fn visual_key(&mut self, c: char, buf: &mut EditBuffer) -> ViAction {
+        match c {
+            'd' | 'x' | 'c' | 'y' => {
+                let op = if c == 'x' { 'd' } else { c };
+                let pos = buf.cursor_pos();
+                let start = self.visual_start;
+                buf.clear_mark();
+                self.mode = ViMode::Normal;
+                move_to(buf, start);
+                let action = self.operate(op, buf, pos, Range::Inclusive);
+                // repeating a visual change is not supported.
+                self.last_change.clear();
+                action
+            }
+            'v' => {
+                self.mode = ViMode::Normal;
+                buf.clear_mark();
+                self.done()
+            }
+            _ => self.motion_or_operator(c, buf),
+        }
+    }
+    /// move the cursor by the motion, or apply the pending operator to the text moved over.
+    fn motion_or_operator(&mut self, c: char, buf: &mut EditBuffer) -> ViAction {
+        let count = match (self.operator, self.count.take()) {
+            (Some((_, Some(n1))), Some(n2)) => Some(n1 * n2),
+            (Some((_, n1)), n2) => n1.or(n2),
+            (None, n) => n,
+        };
+        let pos = buf.cursor_pos();
+        let text = buf.text();
+        let (target, range) = match (self.operator, c) {
+            // `cw` changes to the end of the word like `ce`.
+            (Some(('c', _)), 'w') if char_class_at(text, pos) != CharClass::Space => {
+                match motion(text, pos, 'e', count) {
+                    Some(target) => target,
+                    None => return self.done(),
+                }
+            }
+            (Some(_), 'w') => {
+                // the last word of a line is taken without the newline.
+                let mut target = pos;
+                for _ in 0..count.unwrap_or(1) {
+                    let next = next_word_start(text, target);
+                    if text.char_to_line(next) > text.char_to_line(target) {
+                        let y = text.char_to_line(target);
+                        target = (text.line_to_char(y) + text.line_len(y)).max(target + 1);
+                        target = target.min(text.len_chars());
+                        break;
+                    }
+                    target = next;
+                }
+                (target, Range::Exclusive)
+            }
+            _ => match motion(text, pos, c, count) {
+                Some(target) => target,
+                None => return self.done(),
+            },
+        };
+        match self.operator {
+            Some((op, _)) => {
+                self.operator = None;
+                self.operate(op, buf, target, range)
+            }
+            None => {
+                move_to(buf, target);
+                self.done()
+            }
+        }
+    }
+    /// apply the operator to the text between the cursor and `target`.
+    fn operate(&mut self, op: char, buf: &mut EditBuffer, target: usize, range: Range) -> ViAction {
+        self.operator = None;
+        let text = buf.text();
+        let pos = buf.cursor_pos();
+        let (start, end) = (pos.min(target), pos.max(target));
+        if range == Range::Linewise {
+            let (y1, y2) = (text.char_to_line(start), text.char_to_line(end));
+            let lines: Vec<String> = (y1..=y2).map(|y| text.line(y)).collect();
+            self.register = lines.join("\n") + "\n";
+            self.linewise = true;
+            let last = text.len_lines() - 1;
+            let (start, end) = match op {
+                'c' => (
+                    text.line_to_char(y1),
+                    text.line_to_char(y2) + text.line_len(y2),
+                ),
+                _ if y2 < last => (text.line_to_char(y1), text.line_to_char(y2 + 1)),
+                // the last line has no newline, delete the one before.
+                _ if y1 > 0 => (text.line_to_char(y1) - 1, text.len_chars()),
+                _ => (0, text.len_chars()),
+            };
+            return match op {
+                'y' => {
+                    buf.goto(buf.cur_x(), y1);
+                    self.done()
+                }
+                'd' => {
+                    buf.undo_boundary();
+                    buf.replace_range(start, end, "");
+                    let y = y1.min(buf.text().len_lines() - 1);
+                    let pos = first_non_blank(buf.text(), y);
+                    move_to(buf, pos);
+                    self.changed()
+                }
+                _ => {
+                    buf.undo_boundary();
+                    buf.replace_range(start, end, "");
+                    self.insert(true)
+                }
+            };
+        }
+        let end = match range {
+            Range::Inclusive => (end + 1).min(text.len_chars()),
+            _ => end,
+        };
+        self.register = text.slice(start, end);
+        self.linewise = false;
+        match op {
+            'y' => {
+                move_to(buf, start);
+                self.done()
+            }
+            'd' => {
+                buf.undo_boundary();
+                buf.replace_range(start, end, "");
+                self.changed()
+            }
+            _ => {
+                buf.undo_boundary();
+                buf.replace_range(start, end, "");
+                self.insert(true)
+            }
+        }
+    }
+    /// put the register after (`p`) or before (`P`) the cursor `n` times.
+    fn paste(&mut self, buf: &mut EditBuffer, after: bool, n: usize) {
+        let s = self.register.repeat(n);
+        let text = buf.text();
+        let (x, y) = (buf.cur_x(), buf.cur_y());
+        if self.linewise {
+            let line = if after { y + 1 } else { y };
+            if line < text.len_lines() {
+                let at = text.line_to_char(line);
+                buf.replace_range(at, at, &s);
+            } else {
+                // after the last line, which has no newline
+                let at = text.len_chars();
+                buf.replace_range(at, at, &format!("\n{}", s.trim_end_matches('\n')));
+            }
+            let pos = first_non_blank(buf.text(), line);
+            move_to(buf, pos);
+        } else {
+            let at = if after && text.line_len(y) > 0 {
+                text.pos(x, y) + 1
+            } else {
+                text.pos(x, y)
+            };
+            buf.replace_range(at, at, &s);
+            move_to(buf, at + s.chars().count() - 1);
+        }
+    }
+    fn take_count(&mut self) -> usize {
+        let n1 = self.operator.and_then(|(_, n)| n).unwrap_or(1);
+        n1 * self.count.take().unwrap_or(1)
+    }
+    fn reset(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.prefix = None;
+    }
+    /// a command which does not change the text is complete.
+    fn done(&mut self) -> ViAction {
+        self.keys.clear();
+        self.reset();
+        ViAction::Done
+    }
+    /// a change is complete, it can be repeated by `.`.
+    fn changed(&mut self) -> ViAction {
+        self.last_change = std::mem::take(&mut self.keys);
+        self.reset();
+        ViAction::Changed
+    }
+    /// switch to insert mode. The typed text is a part of the change.
+    fn insert(&mut self, changed: bool) -> ViAction {
+        self.mode = ViMode::Insert;
+        self.in_change = true;
+        self.reset();
+        if changed {
+            ViAction::Changed
+        } else {
+            ViAction::Done
+        }
+    }
+}
+
+fn move_to(buf: &mut EditBuffer, pos: usize) {
+    let (x, y) = buf.text().xy(pos);
+    buf.goto(x, y);
+}
+
+/// in normal mode the cursor is on a char, not after the end of the line.
+fn stay_on_char(buf: &mut EditBuffer) {
+    let len = buf.current_line_len();
+    if len > 0 && buf.cur_x() >= len {
+        buf.goto(len - 1, buf.cur_y());
+    }
+}
+
+/// target of the motion `c` from `pos`. None if `c` is not a motion.
+fn motion(text: &Text, pos: usize, c: char, count: Option<usize>) -> Option<(usize, Range)> {
+    let n = count.unwrap_or(1);
+    let (x, y) = text.xy(pos);
+    let last = text.len_lines() - 1;
+    let line_end = |y: usize| text.line_to_char(y) + text.line_len(y);
+    let vertical = |y: usize| text.pos(x.min(text.line_len(y)), y);
+    let target = match c {
+        'h' => (pos - x.min(n), Range::Exclusive),
+        'l' | ' ' => (pos + (text.line_len(y) - x).min(n), Range::Exclusive),
+        'j' => (vertical((y + n).min(last)), Range::Linewise),
+        'k' => (vertical(y.saturating_sub(n)), Range::Linewise),
+        '0' => (pos - x, Range::Exclusive),
+        '^' => (first_non_blank(text, y), Range::Exclusive),
+        '$' => (line_end((y + n - 1).min(last)), Range::Exclusive),
+        'w' => (
+            (0..n).fold(pos, |p, _| next_word_start(text, p)),
+            Range::Exclusive,
+        ),
+        'b' => (
+            (0..n).fold(pos, |p, _| prev_word_start(text, p)),
+            Range::Exclusive,
+        ),
+        'e' => ((0..n).fold(pos, |p, _| word_end(text, p)), Range::Inclusive),
+        'G' => {
+            let y = count.map_or(last, |n| n.saturating_sub(1).min(last));
+            (first_non_blank(text, y), Range::Linewise)
+        }
+        'g' => {
+            let y = count.map_or(0, |n| n.saturating_sub(1).min(last));
+            (first_non_blank(text, y), Range::Linewise)
+        }
+        _ => return None,
+    };
+    Some(target)
+}
+
+fn char_class_at(text: &Text, pos: usize) -> CharClass {
+    if pos < text.len_chars() {
+        char_class(text.char(pos))
+    } else {
+        CharClass::Space
+    }
+}
+
+/// char offset of the first non-blank char of the line `y`.
+fn first_non_blank(text: &Text, y: usize) -> usize {
+    let blanks = text
+        .line(y)
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .count();
+    text.line_to_char(y) + blanks
+}
+
+/// start of the next word.
+fn next_word_start(text: &Text, pos: usize) -> usize {
+    let len = text.len_chars();
+    let mut p = pos;
+    let class = char_class_at(text, p);
+    if class != CharClass::Space {
+        while p < len && char_class_at(text, p) == class {
+            p += 1;
+        }
+    }
+    while p < len && char_class_at(text, p) == CharClass::Space {
+        p += 1;
+    }
+    p
+}
+
+/// start of the word before `pos`.
+fn prev_word_start(text: &Text, pos: usize) -> usize {
+    let mut p = pos;
+    while p > 0 && char_class_at(text, p - 1) == CharClass::Space {
+        p -= 1;
+    }
+    if p > 0 {
+        let class = char_class_at(text, p - 1);
+        while p > 0 && char_class_at(text, p - 1) == class {
+            p -= 1;
+        }
+    }
+    p
+}
+
+/// last char of the word after `pos`.
+fn word_end(text: &Text, pos: usize) -> usize {
+    let len = text.len_chars();
+    let mut p = pos + 1;
+    while p < len && char_class_at(text, p) == CharClass::Space {
+        p += 1;
+    }
+    if p >= len {
+        return len.saturating_sub(1).max(pos);
+    }
+    let class = char_class_at(text, p);
+    while p + 1 < len && char_class_at(text, p + 1) == class {
+        p += 1;
+    }
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> EditBuffer {
+        let layout = Screen {
+            width: 80,
+            height: 24,
+        }
+        .layout();
+        let mut buf = EditBuffer::new(layout.editor);
+        buf.new_buffer();
+        buf.insert_str(text);
+        buf.goto(0, 0);
+        buf
+    }
+    fn input(vi: &mut Vi, buf: &mut EditBuffer, keys: &str) -> ViAction {
+        let mut action = ViAction::Done;
+        for c in keys.chars() {
+            let key = if c == '\u{1b}' {
+                Key::Esc
+            } else {
+                Key::Char(c)
+            };
+            action = vi.input(key, buf);
+        }
+        action
+    }
+
+    #[test]
+    fn motions() {
+        let mut vi = Vi::new();
+        let mut buf = buffer("foo bar.baz\n  qux\nend");
+        input(&mut vi, &mut buf, "w");
+        assert_eq!(buf.cursor_pos(), 4);
+        input(&mut vi, &mut buf, "2w");
+        assert_eq!(buf.cursor_pos(), 8);
+        input(&mut vi, &mut buf, "e");
+        assert_eq!(buf.cursor_pos(), 10);
+        input(&mut vi, &mut buf, "b");
+        assert_eq!(buf.cursor_pos(), 8);
+        input(&mut vi, &mut buf, "$");
+        assert_eq!(buf.cursor_pos(), 10);
+        input(&mut vi, &mut buf, "j^");
+        assert_eq!((buf.cur_x(), buf.cur_y()), (2, 1));
+        input(&mut vi, &mut buf, "G");
+        assert_eq!(buf.cur_y(), 2);
+        input(&mut vi, &mut buf, "gg");
+        assert_eq!(buf.cur_y(), 0);
+        input(&mut vi, &mut buf, "2G0");
+        assert_eq!((buf.cur_x(), buf.cur_y()), (0, 1));
+    }
+    #[test]
+    fn operators_and_counts() {
+        let mut vi = Vi::new();
+        let mut buf = buffer("one two three four\nfive\nsix");
+        assert_eq!(input(&mut vi, &mut buf, "dw"), ViAction::Changed);
+        assert_eq!(buf.text().line(0), "two three four");
+        input(&mut vi, &mut buf, "2dw");
+        assert_eq!(buf.text().line(0), "four");
+        // the last word of a line is deleted without the newline.
+        input(&mut vi, &mut buf, "dw");
+        assert_eq!(buf.text().to_string(), "\nfive\nsix");
+        input(&mut vi, &mut buf, "jdd");
+        assert_eq!(buf.text().to_string(), "\nsix");
+        input(&mut vi, &mut buf, "yyP");
+        assert_eq!(buf.text().to_string(), "\nsix\nsix");
+        input(&mut vi, &mut buf, "2x");
+        assert_eq!(buf.text().to_string(), "\nx\nsix");
+        input(&mut vi, &mut buf, "u");
+        assert_eq!(buf.text().to_string(), "\nsix\nsix");
+        input(&mut vi, &mut buf, "ggdG");
+        assert_eq!(buf.text().to_string(), "");
+    }
+    #[test]
+    fn change_and_repeat() {
+        let mut vi = Vi::new();
+        let mut buf = buffer("foo bar baz");
+        assert_eq!(input(&mut vi, &mut buf, "cw"), ViAction::Changed);
+        assert_eq!(vi.mode(), ViMode::Insert);
+        assert_eq!(buf.text().to_string(), " bar baz");
+        // keys in insert mode are handled by the editor.
+        assert_eq!(vi.input(Key::Char('X'), &mut buf), ViAction::PassThrough);
+        buf.insert_char('X');
+        buf.goto(1, 0);
+        input(&mut vi, &mut buf, "\u{1b}");
+        assert_eq!(vi.mode(), ViMode::Normal);
+        assert_eq!(buf.cur_x(), 0);
+        input(&mut vi, &mut buf, "w");
+        let keys: Vec<Key> = "cwX\u{1b}"
+            .chars()
+            .map(|c| {
+                if c == '\u{1b}' {
+                    Key::Esc
+                } else {
+                    Key::Char(c)
+                }
+            })
+            .collect();
+        assert_eq!(input(&mut vi, &mut buf, "."), ViAction::Repeat(keys));
+        input(&mut vi, &mut buf, "x");
+        let keys = vec![Key::Char('3'), Key::Char('x')];
+        assert_eq!(input(&mut vi, &mut buf, "3."), ViAction::Repeat(keys));
+    }
+    #[test]
+    fn visual_mode() {
+        let mut vi = Vi::new();
+        let mut buf = buffer("abc def");
+        input(&mut vi, &mut buf, "lve");
+        assert_eq!(vi.mode(), ViMode::Visual);
+        assert_eq!(input(&mut vi, &mut buf, "d"), ViAction::Changed);
+        assert_eq!(vi.mode(), ViMode::Normal);
+        assert_eq!(buf.text().to_string(), "a def");
+        assert_eq!(buf.mark(), None);
+        input(&mut vi, &mut buf, "vly$p");
+        assert_eq!(buf.text().to_string(), "a def d");
+    }
+    #[test]
+    fn other_keys_pass_through() {
+        let mut vi = Vi::new();
+        let mut buf = buffer("abc");
+        assert_eq!(vi.input(Key::Ctrl('s'), &mut buf), ViAction::PassThrough);
+        assert_eq!(vi.input(Key::Up, &mut buf), ViAction::PassThrough);
+        assert_eq!(input(&mut vi, &mut buf, "q"), ViAction::Done);
+        assert_eq!(buf.text().to_string(), "abc");
+    }
+}
@@ -0,0 +1,370 @@
+use std::io;
+use std::io::Write;
+use std::str;
+
+use unicode_width::UnicodeWidthChar;
+
+/// One character cell of the virtual screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub ch: char, // '\0' for the right half of a wide character
+    pub fg: Option<u8>,
+    pub bg: Option<u8>,
+    pub inverse: bool,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            ch: ' ',
+            fg: None,
+            bg: None,
+            inverse: false,
+        }
+    }
+}
+
+/// In-memory terminal.
+/// Interprets the escape sequences written by ked (cursor movement, clear, colors)
+/// into a grid of cells, so that rendering can be checked without a real terminal.
+/// Coordinates are 1-based like `cursor::Goto`.
+pub struct VirtualScreen {
+    width: u16,
+    height: u16,
+    cells: Vec<Vec<Cell>>,
+    cur_x: u16, // 0-based
+    cur_y: u16, // 0-based
+    cursor_visible: bool,
+    pen: Cell,        // attributes of the characters to be written
+    pending: Vec<u8>, // incomplete escape sequence or UTF-8 character
+}
+
+impl VirtualScreen {
+    pub fn new(width: u16, height: u16) -> Self {
+        VirtualScreen {
+            width,
+            height,
+            cells: vec![vec![Cell::default(); width as usize]; height as usize],
+            cur_x: 0,
+            cur_y: 0,
+            cursor_visible: true,
+            pen: Cell::default(),
+            pending: vec![],
+        }
+    }
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+    /// change the size. The content is kept as far as it fits.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.cells.resize(height as usize, vec![]);
+        for row in self.cells.iter_mut() {
+            row.resize(width as usize, Cell::default());
+        }
+        self.width = width;
+        self.height = height;
+        self.cur_x = self.cur_x.min(width.saturating_sub(1));
+        self.cur_y = self.cur_y.min(height.saturating_sub(1));
+    }
+    /// cursor position (x, y). After writing at the last column, the cursor stays there.
+    pub fn cursor(&self) -> (u16, u16) {
+        (
+            self.cur_x.min(self.width.saturating_sub(1)) + 1,
+            self.cur_y + 1,
+        )
+    }
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+    pub fn cell(&self, x: u16, y: u16) -> Cell {
+        self.cells[y as usize - 1][x as usize - 1]
+    }
+    /// text on the row `y` without trailing spaces.
+    pub fn row(&self, y: u16) -> String {
+        let row: String = self.cells[y as usize - 1]
+            .iter()
+            .filter(|cell| cell.ch != '\0')
+            .map(|cell| cell.ch)
+            .collect();
+        row.trim_end().to_string()
+    }
+    /// text of the whole screen, rows are separated by '\n'.
+    pub fn text(&self) -> String {
+        (1..=self.height)
+            .map(|y| self.row(y))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn clear_cells(&mut self, y: u16, from: u16, to: u16) {
+        if let Some(row) = self.cells.get_mut(y as usize) {
+            for cell in row.iter_mut().take(to as usize).skip(from as usize) {
+                *cell = Cell::default();
+            }
+        }
+    }
+    fn put_char(&mut self, ch: char) {
+        let w = ch.width().unwrap_or(0) as u16;
+        if w == 0 || self.width == 0 || self.height == 0 {
+            return;
+        }
+        if self.cur_x + w > self.width {
+            // auto wrap
+            self.cur_x = 0;
+            self.line_feed();
+        }
+        let (x, y) = (self.cur_x as usize, self.cur_y as usize);
+        let row = &mut self.cells[y];
+        // a wide character partly overwritten is erased.
+        if row[x].ch == '\0' && x > 0 {
+            row[x - 1].ch = ' ';
+        }
+        let end = x + w as usize;
+        if end < row.len() && row[end].ch == '\0' {
+            row[end].ch = ' ';
+        }
+        row[x] = Cell { ch, ..self.pen };
+        if w == 2 {
+            row[x + 1] = Cell {
+                ch: '\0',
+                ..self.pen
+            };
+        }
+        self.cur_x += w;
+    }
+    fn line_feed(&mut self) {
+        if self.cur_y + 1 < self.height {
+            self.cur_y += 1;
+        } else {
+            self.cells.remove(0);
+            self.cells.push(vec![Cell::default(); self.width as usize]);
+        }
+    }
+    fn goto(&mut self, x: u16, y: u16) {
+        self.cur_x = x.max(1).min(self.width.max(1)) - 1;
+        self.cur_y = y.max(1).min(self.height.max(1)) - 1;
+    }
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        let mut params = params.iter();
+        while let Some(p) = params.next() {
+            match p {
+                0 => self.pen = Cell::default(),
+                7 => self.pen.inverse = true,
+                27 => self.pen.inverse = false,
+                30..=37 => self.pen.fg = Some((p - 30) as u8),
+                39 => self.pen.fg = None,
+                40..=47 => self.pen.bg = Some((p - 40) as u8),
+                49 => self.pen.bg = None,
+                38 | 48 => {
+                    // 38;5;n or 48;5;n
+                    let color = match (params.next(), params.next()) {
+                        (Some(5), Some(n)) => Some(*n as u8),
+                        _ => None,
+                    };
+                    if *p == 38 {
+                        self.pen.fg = color;
+                    } else {
+                        self.pen.bg = color;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    /// execute CSI sequence `ESC [ params final`.
+    fn control_sequence(&mut self, params: &str, final_byte: u8) {
+        let private = params.starts_with('?');
+        let nums: Vec<u16> = params
+            .trim_start_matches('?')
+            .split(';')
+            .map(|n| n.parse().unwrap_or(0))
+            .collect();
+        let n = |i: usize| nums.get(i).copied().filter(|n| *n > 0).unwrap_or(1);
+        let (x, y) = (self.cur_x, self.cur_y);
+        match final_byte {
+            b'H' | b'f' => self.goto(n(1), n(0)),
+            b'A' => self.goto(x + 1, (y + 1).saturating_sub(n(0))),
+            b'B' => self.goto(x + 1, y + 1 + n(0)),
+            b'C' => self.goto(x + 1 + n(0), y + 1),
+            b'D' => self.goto((x + 1).saturating_sub(n(0)), y + 1),
+            b'J' => match nums[0] {
+                0 => {
+                    self.clear_cells(y, x, self.width);
+                    for y in y + 1..self.height {
+                        self.clear_cells(y, 0, self.width);
+                    }
+                }
+                1 => {
+                    for y in 0..y {
+                        self.clear_cells(y, 0, self.width);
+                    }
+                    self.clear_cells(y, 0, x + 1);
+                }
+                _ => {
+                    for y in 0..self.height {
+                        self.clear_cells(y, 0, self.width);
+                    }
+                }
+            },
+            b'K' => match nums[0] {
+                0 => self.clear_cells(y, x, self.width),
+                1 => self.clear_cells(y, 0, x + 1),
+                _ => self.clear_cells(y, 0, self.width),
+            },
+            b'm' => self.select_graphic_rendition(&nums),
+            b'h' | b'l' if private && nums[0] == 25 => self.cursor_visible = final_byte == b'h',
+            _ => {}
+        }
+    }
+    /// interpret `pending` bytes as far as they are complete.
+    fn process(&mut self) {
+        let bytes = std::mem::take(&mut self.pending);
+        let mut i = 0;
+        while i < bytes.len() {
+            let consumed = match bytes[i] {
+                0x1b => self.escape(&bytes[i..]),
+                b'\r' => {
+                    self.cur_x = 0;
+                    Some(1)
+                }
+                b'\n' => {
+                    self.line_feed();
+                    Some(1)
+                }
+                0x08 => {
+                    self.cur_x = self.cur_x.saturating_sub(1);
+                    Some(1)
+                }
+                b if b < 0x20 || b == 0x7f => Some(1),
+                _ => {
+                    let len = match bytes[i] {
+                        0xf0..=0xff => 4,
+                        0xe0..=0xef => 3,
+                        0xc0..=0xdf => 2,
+                        _ => 1,
+                    };
+                    if i + len > bytes.len() {
+                        None
+                    } else {
+                        let s = String::from_utf8_lossy(&bytes[i..i + len]).into_owned();
+                        for ch in s.chars() {
+                            self.put_char(ch);
+                        }
+                        Some(len)
+                    }
+                }
+            };
+            match consumed {
+                Some(n) => i += n,
+                None => break,
+            }
+        }
+        self.pending = bytes[i..].to_vec();
+    }
+    /// interpret an escape sequence. Returns the length, None if incomplete.
+    fn escape(&mut self, bytes: &[u8]) -> Option<usize> {
+        match bytes.get(1)? {
+            b'[' => {
+                let len = bytes[2..].iter().position(|b| (0x40..=0x7e).contains(b))? + 3;
+                let params = str::from_utf8(&bytes[2..len - 1]).unwrap_or("");
+                self.control_sequence(params, bytes[len - 1]);
+                Some(len)
+            }
+            b']' => {
+                // OSC, terminated by BEL or ESC \
+                let end = bytes
+                    .windows(2)
+                    .position(|w| w[0] == 0x07 || w == b"\x1b\\");
+                match end {
+                    Some(end) if bytes[end] == 0x07 => Some(end + 1),
+                    Some(end) => Some(end + 2),
+                    None if bytes.last() == Some(&0x07) => Some(bytes.len()),
+                    None => None,
+                }
+            }
+            _ => Some(2),
+        }
+    }
+}
+
+impl Write for VirtualScreen {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        self.process();
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use termion::*;
+
+    #[test]
+    fn goto_and_clear() {
+        let mut screen = VirtualScreen::new(10, 3);
+        write!(screen, "{}abc{}de", cursor::Goto(3, 2), cursor::Goto(1, 3)).unwrap();
+        assert_eq!(screen.text(), "\n  abc\nde");
+        assert_eq!(screen.cursor(), (3, 3));
+        write!(screen, "{}{}", clear::All, cursor::Goto(1, 1)).unwrap();
+        assert_eq!(screen.text(), "\n\n");
+        assert_eq!(screen.cursor(), (1, 1));
+    }
+    #[test]
+    fn wide_chars() {
+        let mut screen = VirtualScreen::new(6, 2);
+        write!(screen, "aあい").unwrap();
+        assert_eq!(screen.row(1), "aあい");
+        assert_eq!(screen.cursor(), (6, 1));
+        write!(screen, "b").unwrap();
+        assert_eq!(screen.cursor(), (6, 1));
+        assert_eq!(screen.cell(3, 1).ch, '\0');
+        // overwrite the right half of 'あ'
+        write!(screen, "{}x", cursor::Goto(3, 1)).unwrap();
+        assert_eq!(screen.row(1), "a xいb");
+        // no room for 'う' at the end of the line
+        write!(screen, "{}う", cursor::Goto(6, 1)).unwrap();
+        assert_eq!(screen.row(2), "う");
+    }
+    #[test]
+    fn colors_and_invert() {
+        let mut screen = VirtualScreen::new(10, 1);
+        write!(
+            screen,
+            "{}{}a{}{}b{}c",
+            color::Fg(color::Black),
+            color::Bg(color::White),
+            color::Fg(color::Reset),
+            style::Invert,
+            style::NoInvert
+        )
+        .unwrap();
+        assert_eq!(screen.cell(1, 1).fg, Some(0));
+        assert_eq!(screen.cell(1, 1).bg, Some(7));
+        assert_eq!(screen.cell(2, 1).fg, None);
+        assert!(screen.cell(2, 1).inverse);
+        assert!(!screen.cell(3, 1).inverse);
+    }
+    #[test]
+    fn split_writes() {
+        let mut screen = VirtualScreen::new(10, 2);
+        let bytes = format!("{}あ{}", cursor::Goto(2, 2), cursor::Hide).into_bytes();
+        for b in bytes {
+            screen.write_all(&[b]).unwrap();
+        }
+        assert_eq!(screen.row(2), " あ");
+        assert!(!screen.cursor_visible());
+    }
+    #[test]
+    fn osc_is_ignored() {
+        let mut screen = VirtualScreen::new(10, 1);
+        write!(screen, "a{}b", crate::clipboard::osc52("foo")).unwrap();
+        assert_eq!(screen.row(1), "ab");
+    }
+}
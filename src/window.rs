@@ -7,6 +7,7 @@ pub struct Window {
     y: u16,
     width: u16,
     height: u16,
+    left: u16,  // columns left of the text, such as the line number gutter
     cur_x: u16, // cursor position: relative coodinates on the window, 0-index-ed.
     cur_y: u16,
     screen: Screen, // Screen information is cloned at the initalizing.
@@ -15,18 +16,19 @@ pub struct Window {
 impl Window {
     pub fn new(x: u16, y: u16, width: u16, height: u16, screen: Screen) -> Self {
         Window {
-            x: x,
-            y: y,
-            width: width,
-            height: height,
+            x,
+            y,
+            width,
+            height,
+            left: 0,
             cur_x: 0,
             cur_y: 0,
-            screen: screen,
+            screen,
         }
     }
     /// return cursor x position on the screen coodinate.
     pub fn scr_cur_x(&self) -> u16 {
-        self.cur_x + self.x
+        self.cur_x + self.left + self.x
     }
     /// return cursor y position on the screen coodinate.
     pub fn scr_cur_y(&self) -> u16 {
@@ -34,7 +36,7 @@ impl Window {
     }
     /// set cursor x position on the window coodinate.
     pub fn set_cur_x(&mut self, x: u16) {
-        if x < self.width {
+        if x < self.text_width() {
             self.cur_x = x
         }
     }
@@ -44,12 +46,23 @@ impl Window {
             self.cur_y = y
         }
     }
-    pub fn height(&mut self) -> u16 {
+    pub fn height(&self) -> u16 {
         self.height
     }
-    pub fn width(&mut self) -> u16 {
+    pub fn width(&self) -> u16 {
         self.width
     }
+    /// columns for the text, right of `left`.
+    pub fn text_width(&self) -> u16 {
+        self.width - self.left
+    }
+    pub fn left(&self) -> u16 {
+        self.left
+    }
+    /// reserve columns on the left. At least a column is left for the text.
+    pub fn set_left(&mut self, left: u16) {
+        self.left = left.min(self.width.saturating_sub(1));
+    }
     pub fn cur_x(&mut self) -> u16 {
         self.cur_x
     }
@@ -0,0 +1,468 @@
+use crate::*;
+
+/// Cursor and scroll position of a window onto a buffer, in buffer coodinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct View {
+    pub cur_x: usize,
+    pub cur_y: usize,
+    pub begin: usize,       // first line displayed
+    pub begin_row: usize,   // first row of the `begin` line displayed, if wrapped
+    pub left_column: usize, // first display column, for long lines
+}
+
+/// Rectangle on the screen, 1-index-ed screen coodinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// A window showing a buffer of the buffer list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EditWindow {
+    pub buffer: usize, // index in the buffer list
+    pub view: View,
+}
+
+/// Tree of splits. Leaves are the windows, in the order of `Windows::windows`.
+#[derive(Debug)]
+enum Node {
+    Leaf,
+    Split {
+        vertical: bool, // side by side, with a separator column
+        size: u16,      // rows, or columns if vertical, of the first node
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+
+const MIN_HEIGHT: u16 = 2; // a text line and the status line
+const MIN_WIDTH: u16 = 1;
+
+/// Rectangles of the two nodes of a split, with the clamped size of the first one.
+fn split_rects(vertical: bool, size: u16, rect: Rect) -> (Rect, Rect, u16) {
+    if vertical {
+        let total = rect.width.saturating_sub(1); // separator
+        let size = size.min(total.saturating_sub(MIN_WIDTH)).max(MIN_WIDTH);
+        let first = Rect {
+            width: size,
+            ..rect
+        };
+        let second = Rect {
+            x: rect.x + size + 1,
+            width: total.saturating_sub(size),
+            ..rect
+        };
+        (first, second, size)
+    } else {
+        let size = size
+            .min(rect.height.saturating_sub(MIN_HEIGHT))
+            .max(MIN_HEIGHT);
+        let first = Rect {
+            height: size,
+            ..rect
+        };
+        let second = Rect {
+            y: rect.y + size,
+            height: rect.height.saturating_sub(size),
+            ..rect
+        };
+        (first, second, size)
+    }
+}
+
+/// collect the rectangles of the leaves, and the separators of vertical splits.
+fn layout(node: &Node, rect: Rect, rects: &mut Vec<Rect>, separators: &mut Vec<Rect>) {
+    match node {
+        Node::Leaf => rects.push(rect),
+        Node::Split {
+            vertical,
+            size,
+            first,
+            second,
+        } => {
+            let (r1, r2, size) = split_rects(*vertical, *size, rect);
+            layout(first, r1, rects, separators);
+            if *vertical {
+                separators.push(Rect {
+                    x: rect.x + size,
+                    width: 1,
+                    ..rect
+                });
+            }
+            layout(second, r2, rects, separators);
+        }
+    }
+}
+
+/// the `n`th leaf. `n` is decremented by the leaves before it.
+fn leaf_mut<'a>(node: &'a mut Node, n: &mut usize) -> Option<&'a mut Node> {
+    match node {
+        Node::Leaf if *n == 0 => Some(node),
+        Node::Leaf => {
+            *n -= 1;
+            None
+        }
+        Node::Split { first, second, .. } => {
+            leaf_mut(first, n).or_else(move || leaf_mut(second, n))
+        }
+    }
+}
+
+/// remove the `n`th leaf, replacing its parent by the sibling. Returns true if `node` is the leaf.
+fn remove_leaf(node: &mut Node, n: &mut usize) -> bool {
+    let sibling = match node {
+        Node::Leaf if *n == 0 => return true,
+        Node::Leaf => {
+            *n -= 1;
+            return false;
+        }
+        Node::Split { first, second, .. } => {
+            if remove_leaf(first, n) {
+                std::mem::replace(second.as_mut(), Node::Leaf)
+            } else if remove_leaf(second, n) {
+                std::mem::replace(first.as_mut(), Node::Leaf)
+            } else {
+                return false;
+            }
+        }
+    };
+    *node = sibling;
+    false
+}
+
+/// change the size of the nearest split in the direction containing the `n`th leaf.
+/// Returns None if the leaf is not in `node`, Some(true) if resized.
+fn resize_node(
+    node: &mut Node,
+    rect: Rect,
+    n: &mut usize,
+    vertical: bool,
+    delta: i32,
+) -> Option<bool> {
+    match node {
+        Node::Leaf if *n == 0 => Some(false),
+        Node::Leaf => {
+            *n -= 1;
+            None
+        }
+        Node::Split {
+            vertical: v,
+            size,
+            first,
+            second,
+        } => {
+            let (r1, r2, clamped) = split_rects(*v, *size, rect);
+            let in_first = match resize_node(first, r1, n, vertical, delta) {
+                Some(done) if done => return Some(true),
+                Some(_) => true,
+                None => match resize_node(second, r2, n, vertical, delta) {
+                    Some(done) if done => return Some(true),
+                    Some(_) => false,
+                    None => return None,
+                },
+            };
+            if *v != vertical {
+                return Some(false);
+            }
+            let delta = if in_first { delta } else { -delta };
+            let new_size = (clamped as i32 + delta).max(0) as u16;
+            *size = split_rects(*v, new_size, rect).2;
+            Some(*size != clamped)
+        }
+    }
+}
+
+/// Windows splitting the editing area. Each window has a status line at the bottom.
+pub struct Windows {
+    root: Node,
+    windows: Vec<EditWindow>,
+    focus: usize,
+    area: Rect,
+    screen: Screen,
+}
+
+impl Windows {
+    /// one window on `area`, showing the buffer 0.
+    pub fn new(area: Rect, screen: Screen) -> Self {
+        Windows {
+            root: Node::Leaf,
+            windows: vec![EditWindow {
+                buffer: 0,
+                view: View::default(),
+            }],
+            focus: 0,
+            area,
+            screen,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+    pub fn set_focus(&mut self, index: usize) {
+        if index < self.windows.len() {
+            self.focus = index;
+        }
+    }
+    pub fn get(&self, index: usize) -> EditWindow {
+        self.windows[index]
+    }
+    pub fn set(&mut self, index: usize, window: EditWindow) {
+        self.windows[index] = window;
+    }
+    /// the area and the screen are changed by resizing the terminal.
+    pub fn set_area(&mut self, area: Rect, screen: Screen) {
+        self.area = area;
+        self.screen = screen;
+    }
+    /// rectangles of the windows including the status lines, and separators between windows
+    /// side by side.
+    pub fn layout(&self) -> (Vec<Rect>, Vec<Rect>) {
+        let (mut rects, mut separators) = (vec![], vec![]);
+        layout(&self.root, self.area, &mut rects, &mut separators);
+        (rects, separators)
+    }
+    /// window for the text of the window `index`.
+    pub fn text_window(&self, index: usize) -> Window {
+        let r = self.layout().0[index];
+        Window::new(
+            r.x,
+            r.y,
+            r.width,
+            r.height.saturating_sub(1).max(1),
+            self.screen,
+        )
+    }
+    /// window for the status line of the window `index`.
+    pub fn status_window(&self, index: usize) -> Window {
+        let r = self.layout().0[index];
+        Window::new(
+            r.x,
+            r.y + r.height.saturating_sub(1),
+            r.width,
+            1,
+            self.screen,
+        )
+    }
+    /// split the focused window into two, showing the same buffer and view.
+    /// The focus stays on the first one. Returns false if the window is too small.
+    pub fn split(&mut self, vertical: bool) -> bool {
+        let rect = self.layout().0[self.focus];
+        let size = if vertical {
+            if rect.width < MIN_WIDTH * 2 + 1 {
+                return false;
+            }
+            (rect.width - 1) / 2
+        } else {
+            if rect.height < MIN_HEIGHT * 2 {
+                return false;
+            }
+            rect.height / 2
+        };
+        let leaf = leaf_mut(&mut self.root, &mut self.focus.clone()).unwrap();
+        *leaf = Node::Split {
+            vertical,
+            size,
+            first: Box::new(Node::Leaf),
+            second: Box::new(Node::Leaf),
+        };
+        self.windows
+            .insert(self.focus + 1, self.windows[self.focus]);
+        true
+    }
+    /// delete the focused window. The focus moves to the previous window, or the next one.
+    /// Returns false if it is the only window.
+    pub fn delete(&mut self) -> bool {
+        if self.windows.len() == 1 {
+            return false;
+        }
+        remove_leaf(&mut self.root, &mut self.focus.clone());
+        self.windows.remove(self.focus);
+        self.focus = self.focus.saturating_sub(1);
+        true
+    }
+    /// delete all windows except the focused one.
+    pub fn delete_others(&mut self) {
+        self.root = Node::Leaf;
+        self.windows = vec![self.windows[self.focus]];
+        self.focus = 0;
+    }
+    /// make the focused window larger by `delta` rows, or columns if `vertical`.
+    /// Negative `delta` makes it smaller. Returns false if it cannot be resized.
+    pub fn resize(&mut self, vertical: bool, delta: i32) -> bool {
+        let area = self.area;
+        resize_node(
+            &mut self.root,
+            area,
+            &mut self.focus.clone(),
+            vertical,
+            delta,
+        ) == Some(true)
+    }
+    /// `delta` lines were inserted below the line `start` of the buffer, or removed if negative.
+    /// The other windows on the buffer keep showing the same lines.
+    pub fn lines_changed(&mut self, buffer: usize, start: usize, delta: isize) {
+        // lines removed, or joined to the line `start`, move to it.
+        let shift = |y: usize| match y as isize + delta {
+            _ if y <= start => (y, false),
+            y if y <= start as isize => (start, true),
+            y => (y as usize, false),
+        };
+        for (index, window) in self.windows.iter_mut().enumerate() {
+            if index == self.focus || window.buffer != buffer {
+                continue;
+            }
+            let view = &mut window.view;
+            let (begin, removed) = shift(view.begin);
+            if removed {
+                view.begin_row = 0;
+            }
+            view.begin = begin;
+            view.cur_y = shift(view.cur_y).0;
+        }
+    }
+    /// the buffer `index` was removed from the buffer list, and `current` is displayed instead.
+    pub fn buffer_removed(&mut self, index: usize, current: usize) {
+        for window in &mut self.windows {
+            if window.buffer == index {
+                *window = EditWindow {
+                    buffer: current,
+                    view: View::default(),
+                };
+            } else if window.buffer > index {
+                window.buffer -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn windows() -> Windows {
+        let screen = Screen {
+            width: 80,
+            height: 24,
+        };
+        let area = Rect {
+            x: 1,
+            y: 1,
+            width: 80,
+            height: 23,
+        };
+        Windows::new(area, screen)
+    }
+    fn rect(x: u16, y: u16, width: u16, height: u16) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn split_and_delete() {
+        let mut windows = windows();
+        assert!(windows.split(false));
+        let (rects, separators) = windows.layout();
+        assert_eq!(rects, [rect(1, 1, 80, 11), rect(1, 12, 80, 12)]);
+        assert!(separators.is_empty());
+        windows.set_focus(1);
+        assert!(windows.split(true));
+        let (rects, separators) = windows.layout();
+        assert_eq!(rects[1..], [rect(1, 12, 39, 12), rect(41, 12, 40, 12)]);
+        assert_eq!(separators, [rect(40, 12, 1, 12)]);
+        assert_eq!(windows.text_window(2).height(), 11);
+        assert_eq!(windows.status_window(2).y(), 23);
+
+        assert!(windows.delete());
+        assert_eq!(windows.focus(), 0);
+        assert_eq!(
+            windows.layout().0,
+            [rect(1, 1, 80, 11), rect(1, 12, 80, 12)]
+        );
+        windows.delete_others();
+        assert_eq!(windows.layout().0, [rect(1, 1, 80, 23)]);
+        assert!(!windows.delete());
+    }
+    #[test]
+    fn too_small_to_split() {
+        let mut windows = windows();
+        while windows.split(false) {}
+        assert_eq!(windows.len(), 4);
+        assert_eq!(windows.layout().0[0], rect(1, 1, 80, 2));
+        assert!(windows.layout().0.iter().all(|r| r.height >= MIN_HEIGHT));
+    }
+    #[test]
+    fn resize_windows() {
+        let mut windows = windows();
+        windows.split(false);
+        windows.set_focus(1);
+        assert!(windows.resize(false, 3));
+        assert_eq!(windows.layout().0[1], rect(1, 9, 80, 15));
+        // no vertical split to resize.
+        assert!(!windows.resize(true, 1));
+        assert!(windows.resize(false, -100));
+        assert_eq!(windows.layout().0[1], rect(1, 22, 80, 2));
+        assert!(!windows.resize(false, -1));
+    }
+    #[test]
+    fn buffer_removed() {
+        let mut windows = windows();
+        windows.split(true);
+        windows.split(true);
+        let view = View {
+            cur_x: 1,
+            cur_y: 2,
+            begin: 0,
+            begin_row: 0,
+            left_column: 0,
+        };
+        windows.set(1, EditWindow { buffer: 1, view });
+        windows.set(2, EditWindow { buffer: 2, view });
+        windows.buffer_removed(1, 0);
+        assert_eq!(windows.get(1).buffer, 0);
+        assert_eq!(windows.get(1).view, View::default());
+        assert_eq!(windows.get(2), EditWindow { buffer: 1, view });
+    }
+    #[test]
+    fn lines_changed_above_other_windows() {
+        let mut windows = windows();
+        windows.split(false);
+        windows.split(false);
+        let view = View {
+            cur_x: 3,
+            cur_y: 12,
+            begin: 10,
+            begin_row: 1,
+            left_column: 0,
+        };
+        for index in 0..3 {
+            windows.set(index, EditWindow { buffer: 0, view });
+        }
+        windows.set(2, EditWindow { buffer: 1, view });
+        windows.lines_changed(0, 5, 2);
+        assert_eq!(windows.get(0).view, view); // focused
+        assert_eq!(
+            (windows.get(1).view.begin, windows.get(1).view.cur_y),
+            (12, 14)
+        );
+        assert_eq!(windows.get(2).view, view); // another buffer
+        windows.lines_changed(0, 11, -10);
+        let moved = windows.get(1).view;
+        assert_eq!((moved.begin, moved.begin_row, moved.cur_y), (11, 0, 11));
+        // below the change
+        windows.lines_changed(0, 20, -5);
+        assert_eq!(windows.get(1).view, moved);
+    }
+}
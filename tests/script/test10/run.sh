@@ -0,0 +1,23 @@
+#!/bin/sh
+
+DIR=tests/script/test10
+cp $DIR/input.txt $DIR/output.txt
+cargo run -- -s $DIR/macro.json
+# an unknown command is an error
+cargo run -- --headless -s $DIR/macro_fail.json 2> $DIR/error.txt
+if [ "$?" -eq 0 ] || ! grep -q "command 2:no_such_command:unknown command" $DIR/error.txt
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+rm $DIR/error.txt
+diff $DIR/output.txt $DIR/output_ok.txt
+if [ "$?" -eq 0 ]
+then
+    echo "OK"
+    rm $DIR/output.txt
+    exit 0
+else
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
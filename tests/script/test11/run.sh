@@ -0,0 +1,28 @@
+#!/bin/sh
+
+DIR=tests/script/test11
+cp $DIR/input.txt $DIR/output.txt
+cargo run -- -s $DIR/macro.json
+if [ "$?" -ne 0 ]
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+# a failed assertion in a loop is reported with the nested index
+cargo run -- --headless -s $DIR/macro_fail.json 2> $DIR/error.txt
+if [ "$?" -eq 0 ] || ! grep -q "command 2.2:assert_line:expected \"b\", but \"# c\"" $DIR/error.txt
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+rm $DIR/error.txt
+diff $DIR/output.txt $DIR/output_ok.txt
+if [ "$?" -eq 0 ]
+then
+    echo "OK"
+    rm $DIR/output.txt
+    exit 0
+else
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
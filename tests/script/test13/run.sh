@@ -0,0 +1,28 @@
+#!/bin/sh
+
+DIR=tests/script/test13
+cp $DIR/input.txt $DIR/output.txt
+cargo run -- --config $DIR/config.json -s $DIR/macro.json
+if [ "$?" -ne 0 ]
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+# an invalid config file is reported with its name
+cargo run -- --config $DIR/config_invalid.json -s $DIR/macro.json 2> $DIR/error.txt
+if [ "$?" -eq 0 ] || ! grep -q "config_invalid.json:tab_width must be between 1 and 16, but 0" $DIR/error.txt
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+rm $DIR/error.txt
+diff $DIR/output.txt $DIR/output_ok.txt
+if [ "$?" -eq 0 ]
+then
+    echo "OK"
+    rm $DIR/output.txt
+    exit 0
+else
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
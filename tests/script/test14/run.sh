@@ -0,0 +1,20 @@
+#!/bin/sh
+
+DIR=tests/script/test14
+cp $DIR/input.txt $DIR/output.txt
+cargo run -- -s $DIR/macro.json
+if [ "$?" -ne 0 ]
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+diff $DIR/output.txt $DIR/output_ok.txt
+if [ "$?" -eq 0 ]
+then
+    echo "OK"
+    rm $DIR/output.txt
+    exit 0
+else
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
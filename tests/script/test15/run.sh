@@ -0,0 +1,28 @@
+#!/bin/sh
+
+DIR=tests/script/test15
+cp $DIR/input.txt $DIR/output.txt
+cargo run -- -s $DIR/macro.json
+if [ "$?" -ne 0 ]
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+# an error of the script is reported
+cargo run -- --headless -s $DIR/macro_fail.json 2> $DIR/error.txt
+if [ "$?" -eq 0 ] || ! grep -q "command 2:goto_line:invalid position:3:x" $DIR/error.txt
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+rm $DIR/error.txt
+diff $DIR/output.txt $DIR/output_ok.txt
+if [ "$?" -eq 0 ]
+then
+    echo "OK"
+    rm $DIR/output.txt
+    exit 0
+else
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
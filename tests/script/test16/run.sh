@@ -0,0 +1,29 @@
+#!/bin/sh
+
+DIR=tests/script/test16
+cp $DIR/input1.txt $DIR/output1.txt
+cp $DIR/input2.txt $DIR/output2.txt
+cargo run -- -s $DIR/macro.json
+if [ "$?" -ne 0 ]
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+# an unknown buffer name is an error
+cargo run -- --headless -s $DIR/macro_fail.json 2> $DIR/error.txt
+if [ "$?" -eq 0 ] || ! grep -q "command 1:switch_buffer:no buffer:no_such_buffer" $DIR/error.txt
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+rm $DIR/error.txt
+diff $DIR/output1.txt $DIR/output1_ok.txt && diff $DIR/output2.txt $DIR/output2_ok.txt
+if [ "$?" -eq 0 ]
+then
+    echo "OK"
+    rm $DIR/output1.txt $DIR/output2.txt
+    exit 0
+else
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
@@ -0,0 +1,19 @@
+#!/bin/sh
+
+DIR=tests/script/test4
+export XDG_CACHE_HOME=$DIR/cache
+cp $DIR/input.txt $DIR/output.txt
+cargo run -- -s $DIR/macro.json
+# undo history is restored after restarting
+cargo run -- -s $DIR/macro_undo.json
+diff $DIR/output.txt $DIR/output_ok.txt
+if [ "$?" -eq 0 ]
+then
+    echo "OK"
+    rm -r $DIR/output.txt $DIR/cache
+    exit 0
+else
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+
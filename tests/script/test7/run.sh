@@ -0,0 +1,25 @@
+#!/bin/sh
+
+DIR=tests/script/test7
+# fake clipboard commands on PATH
+export PATH=$PWD/$DIR/bin:$PATH
+export FAKE_CLIPBOARD=$PWD/$DIR/clipboard.txt
+export KED_CLIPBOARD_COPY=fakecopy
+export KED_CLIPBOARD_PASTE=fakepaste
+cp $DIR/input.txt $DIR/output.txt
+cargo run -- -s $DIR/macro.json
+diff $DIR/clipboard.txt $DIR/clipboard_ok.txt || exit 1
+# text copied by other program is pasted
+printf 'pasted' > $DIR/clipboard.txt
+cargo run -- -s $DIR/macro_paste.json
+diff $DIR/output.txt $DIR/output_ok.txt
+if [ "$?" -eq 0 ]
+then
+    echo "OK"
+    rm $DIR/output.txt $DIR/clipboard.txt
+    exit 0
+else
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+
@@ -0,0 +1,30 @@
+#!/bin/sh
+
+DIR=tests/script/test9
+cp $DIR/input.txt $DIR/output.txt
+# stdout is not a terminal, so the script runs headless and writes nothing to stdout.
+cargo run -- -s $DIR/macro.json > $DIR/stdout.txt
+if [ "$?" -ne 0 ] || [ -s $DIR/stdout.txt ]
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+rm $DIR/stdout.txt
+# a failed command is reported with its index
+cargo run -- --headless -s $DIR/macro_fail.json 2> $DIR/error.txt
+if [ "$?" -eq 0 ] || ! grep -q "command 2:open_file" $DIR/error.txt
+then
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi
+rm $DIR/error.txt
+diff $DIR/output.txt $DIR/output_ok.txt
+if [ "$?" -eq 0 ]
+then
+    echo "OK"
+    rm $DIR/output.txt
+    exit 0
+else
+    echo "******************** TEST FAIL *************************"
+    exit 1
+fi